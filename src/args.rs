@@ -1,9 +1,11 @@
 use structopt::StructOpt;
 
-use crate::commands::{test, version};
+use crate::commands::bar::{self, BarFormat};
+use crate::commands::{self, alerts, forecast, health, service, test, version};
+use crate::daemon::config::LoadedConfig;
 use crate::daemon::{self, config, Config};
-use crate::print_warn;
-use crate::weather::weather::{AlertProperties, Severity};
+use crate::weather::weather::Severity;
+use crate::{print_fatal, print_info, print_warn};
 
 #[derive(StructOpt)]
 #[structopt(
@@ -23,6 +25,10 @@ enum NWSDCommand {
     InitConfig {
         #[structopt(name = "config directory")]
         config_directory: Option<String>,
+
+        /// Replace an existing config file
+        #[structopt(long)]
+        force: bool,
     },
     #[structopt(name = "version", alias = "v")]
     Version {},
@@ -34,41 +40,235 @@ enum NWSDCommand {
         #[structopt(short, long, name = "config directory")]
         config_directory: Option<String>,
     },
+    /// Fetch and print the active alerts once
+    #[structopt(name = "alerts", alias = "a")]
+    Alerts {
+        /// Print the alerts as JSON
+        #[structopt(long)]
+        json: bool,
+
+        /// Only show alerts at or above this severity
+        #[structopt(short, long, parse(try_from_str = parse_severity))]
+        severity: Option<Severity>,
+
+        /// Exit with code 2 when an alert at or above this severity is active
+        #[structopt(long, parse(try_from_str = parse_severity))]
+        fail_on: Option<Severity>,
+
+        /// Print the full alert description under each headline
+        #[structopt(long)]
+        detail: bool,
+
+        #[structopt(short, long, name = "config directory")]
+        config_directory: Option<String>,
+    },
+    /// Print the current conditions and forecast
+    #[structopt(name = "forecast", alias = "f")]
+    Forecast {
+        /// Show the hourly forecast instead of day and night periods
+        #[structopt(long)]
+        hourly: bool,
+
+        /// How many forecast periods to show
+        #[structopt(short, long, default_value = "4")]
+        periods: usize,
+
+        /// Print the detailed forecast under each period
+        #[structopt(long)]
+        detail: bool,
+
+        #[structopt(short, long, name = "config directory")]
+        config_directory: Option<String>,
+    },
+    /// Continuously print a summary of active alerts for a status bar
+    #[structopt(name = "bar")]
+    Bar {
+        /// One of waybar, i3blocks or polybar
+        #[structopt(short, long, default_value = "waybar", parse(try_from_str))]
+        format: BarFormat,
+
+        /// Print a single update and exit, for bars that poll on an interval
+        #[structopt(long)]
+        once: bool,
+
+        #[structopt(short, long, name = "config directory")]
+        config_directory: Option<String>,
+    },
+    /// Report whether the running daemon is polling, exits with 1 if it isn't running or its
+    /// last successful poll is too old
+    #[structopt(name = "health")]
+    Health {
+        /// Seconds since the last successful poll after which the daemon is unhealthy, defaults
+        /// to three of the longest poll intervals
+        #[structopt(long)]
+        max_age: Option<u64>,
+
+        #[structopt(short, long, name = "config directory")]
+        config_directory: Option<String>,
+    },
+    /// Write a systemd user service that runs the daemon
+    #[structopt(name = "install-service")]
+    InstallService {
+        #[structopt(short, long, name = "config directory")]
+        config_directory: Option<String>,
+
+        /// Replace an existing service file
+        #[structopt(long)]
+        force: bool,
+    },
+    /// Inspect the config
+    #[structopt(name = "config")]
+    Config(ConfigCommand),
 }
 
-fn get_config(config_directory: Option<String>) -> Config {
-    let config_option = config::load_config_from_file(config_directory);
+#[derive(StructOpt)]
+enum ConfigCommand {
+    /// Print the effective config after layering the system config, your config and NWSD_*
+    /// environment variables, with where each option came from
+    #[structopt(name = "show")]
+    Show {
+        #[structopt(short, long, name = "config directory")]
+        config_directory: Option<String>,
+    },
+    /// Upgrade the config file to the current version, keeping your values. The old file is saved
+    /// with a .bak extension
+    #[structopt(name = "migrate")]
+    Migrate {
+        #[structopt(short, long, name = "config directory")]
+        config_directory: Option<String>,
+    },
+}
+
+/// Loads the config, `quiet` suppresses informational output for commands whose stdout is
+/// consumed by other programs
+fn get_config(config_directory: Option<String>, quiet: bool) -> Config {
+    get_loaded_config(config_directory, quiet).config
+}
+
+/// Loads the config along with where each option came from, exits if it is unusable
+fn get_loaded_config(config_directory: Option<String>, quiet: bool) -> LoadedConfig {
+    if !quiet {
+        print_info!(
+            "Loading config from: {}",
+            config::check_direcotry(config_directory.clone()).to_string_lossy()
+        );
+    }
 
-    match config_option {
-        Some(config) => config,
-        None => {
-            print_warn!("You have not initalized the config file yet. Please run nwsd init-config to create a config file. Reverting to default config.");
-            crate::daemon::Config::default()
+    match config::load_config(config_directory) {
+        Ok(loaded) => {
+            if !quiet {
+                for warning in &loaded.warnings {
+                    print_warn!(warning);
+                }
+                if loaded.files.is_empty() {
+                    print_warn!("You have not initalized the config file yet. Please run nwsd init-config to create a config file. Reverting to default config.");
+                }
+            }
+            loaded
+        }
+        Err(errors) => {
+            for error in errors {
+                print_fatal!(error);
+            }
+            print_fatal!("Please fix the config file and try again.");
+            std::process::exit(1);
         }
     }
 }
 
+/// Like `Severity::from_str`, but a typo is an error instead of `Unknown`
+fn parse_severity(s: &str) -> Result<Severity, String> {
+    match s.parse::<Severity>()? {
+        Severity::Unknown => Err(format!(
+            "Unknown severity {}. Expected one of extreme, severe, moderate or minor",
+            s
+        )),
+        severity => Ok(severity),
+    }
+}
+
 pub fn parse_args() {
     match NWSDCommand::from_args() {
         NWSDCommand::Run {
             config_directory,
             debug,
         } => {
-            let config = get_config(config_directory);
-            let daemon = daemon::init::init_daemon(config, debug);
+            let config = get_config(config_directory.clone(), false);
+            let daemon = daemon::init::init_daemon(config, config_directory, debug);
             daemon::run::run(daemon)
         }
         NWSDCommand::Version {} => version::print_version(),
-        NWSDCommand::InitConfig { config_directory } => {
-            config::create_default_config(config_directory)
-        }
+        NWSDCommand::InitConfig {
+            config_directory,
+            force,
+        } => config::create_default_config(config_directory, force),
         NWSDCommand::Test {
             severity,
             config_directory,
         } => {
             println!("{:?}", severity);
-            let config = get_config(config_directory);
+            let config = get_config(config_directory, false);
             test::test_alert(&config, &severity)
         }
+        NWSDCommand::Alerts {
+            json,
+            severity,
+            fail_on,
+            detail,
+            config_directory,
+        } => {
+            let config = get_config(config_directory, json);
+            std::process::exit(alerts::list_alerts(
+                &config,
+                severity.as_ref(),
+                fail_on.as_ref(),
+                json,
+                detail,
+            ))
+        }
+        NWSDCommand::Forecast {
+            hourly,
+            periods,
+            detail,
+            config_directory,
+        } => {
+            let config = get_config(config_directory, false);
+            forecast::print_forecast(&config, hourly, periods, detail)
+        }
+        NWSDCommand::Bar {
+            format,
+            once,
+            config_directory,
+        } => {
+            let config = get_config(config_directory, true);
+            bar::run_bar(&config, &format, once)
+        }
+        NWSDCommand::Health {
+            max_age,
+            config_directory,
+        } => {
+            let config = get_config(config_directory, true);
+            std::process::exit(health::check_health(&config, max_age))
+        }
+        NWSDCommand::InstallService {
+            config_directory,
+            force,
+        } => {
+            let config = get_config(config_directory.clone(), false);
+            if let Err(e) = service::install_service(&config, config_directory, force) {
+                print_fatal!(e);
+                std::process::exit(1);
+            }
+        }
+        NWSDCommand::Config(ConfigCommand::Show { config_directory }) => {
+            let loaded = get_loaded_config(config_directory, true);
+            commands::config::show_config(&loaded)
+        }
+        NWSDCommand::Config(ConfigCommand::Migrate { config_directory }) => {
+            if let Err(e) = daemon::migrate::migrate_config(config_directory) {
+                print_fatal!(e);
+                std::process::exit(1);
+            }
+        }
     }
 }
@@ -0,0 +1,117 @@
+use serde::Serialize;
+
+use crate::{
+    daemon::Config,
+    print_done, print_error, print_info,
+    weather::{
+        geometry::Proximity,
+        weather::{
+            fetch_weather_features, get_level_for_severity, get_proximity, AlertProperties,
+            Severity,
+        },
+    },
+};
+
+/// Exit code when every location was fetched and no alert reached the `fail_on` threshold
+pub const EXIT_OK: i32 = 0;
+/// Exit code when the alerts for a location could not be fetched
+pub const EXIT_FETCH_FAILED: i32 = 1;
+/// Exit code when an alert at or above the `fail_on` threshold is active
+pub const EXIT_THRESHOLD_REACHED: i32 = 2;
+
+#[derive(Serialize)]
+struct LocatedAlert<'a> {
+    location: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    proximity: Option<Proximity>,
+    #[serde(flatten)]
+    properties: &'a AlertProperties,
+}
+
+/// Fetches the active alerts once for every configured location and prints them. Returns the
+/// process exit code
+pub fn list_alerts(
+    config: &Config,
+    min_severity: Option<&Severity>,
+    fail_on: Option<&Severity>,
+    json: bool,
+    detail: bool,
+) -> i32 {
+    let min_level = min_severity.map(get_level_for_severity).unwrap_or(0);
+    let mut exit_code = EXIT_OK;
+    let mut located_features = vec![];
+
+    for location in config.get_locations() {
+        let features = match fetch_weather_features(config, &location) {
+            Ok(features) => features,
+            Err(e) => {
+                // Keep stdout parseable when printing JSON
+                if json {
+                    eprintln!("Could not fetch alerts for {}: {}", location.name, e);
+                } else {
+                    print_error!("Could not fetch alerts for {}: {}", location.name, e);
+                }
+                // An active alert is more important to report than a failed fetch
+                if exit_code != EXIT_THRESHOLD_REACHED {
+                    exit_code = EXIT_FETCH_FAILED;
+                }
+                continue;
+            }
+        };
+
+        // The threshold applies to every active alert, not just the ones shown
+        if let Some(threshold) = fail_on {
+            if features.iter().any(|feature| {
+                get_level_for_severity(&feature.properties.severity)
+                    >= get_level_for_severity(threshold)
+            }) {
+                exit_code = EXIT_THRESHOLD_REACHED;
+            }
+        }
+
+        let features: Vec<_> = features
+            .into_iter()
+            .filter(|feature| get_level_for_severity(&feature.properties.severity) >= min_level)
+            .collect();
+
+        located_features.push((location, features));
+    }
+
+    if json {
+        let alerts: Vec<LocatedAlert> = located_features
+            .iter()
+            .flat_map(|(location, features)| {
+                features.iter().map(|feature| LocatedAlert {
+                    location: &location.name,
+                    proximity: get_proximity(feature, location),
+                    properties: &feature.properties,
+                })
+            })
+            .collect();
+        match serde_json::to_string_pretty(&alerts) {
+            Ok(serialized) => println!("{}", serialized),
+            Err(e) => print_error!("Could not serialize alerts: {}", e),
+        }
+        return exit_code;
+    }
+
+    for (location, features) in &located_features {
+        if features.is_empty() {
+            print_done!("No active alerts for {}", location.name);
+            continue;
+        }
+
+        print_info!("Active alerts for {}:", location.name);
+        for feature in features {
+            match get_proximity(feature, location) {
+                Some(proximity) => println!("{} ({})", feature, proximity),
+                None => println!("{}", feature),
+            }
+            if detail {
+                println!("{}\n", feature.properties.description);
+            }
+        }
+    }
+
+    exit_code
+}
@@ -0,0 +1,127 @@
+use std::{str::FromStr, thread, time::Duration};
+
+use serde_json::json;
+
+use crate::{
+    daemon::Config,
+    weather::weather::{
+        fetch_all_weather_features, get_color_for_severity, get_hex_for_color,
+        get_level_for_severity, Feature,
+    },
+};
+
+/// The status bar protocol to emit
+#[derive(Debug)]
+pub enum BarFormat {
+    /// JSON lines for a waybar custom module with `"return-type": "json"`
+    Waybar,
+    /// The i3blocks full_text, short_text and color lines
+    I3blocks,
+    /// A single line using polybar color formatting tags
+    Polybar,
+}
+
+impl FromStr for BarFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "waybar" => Ok(BarFormat::Waybar),
+            "i3blocks" => Ok(BarFormat::I3blocks),
+            "polybar" => Ok(BarFormat::Polybar),
+            _ => Err(format!(
+                "Unknown bar format {}. Expected one of waybar, i3blocks or polybar",
+                s
+            )),
+        }
+    }
+}
+
+/// Summary of the currently active alerts that gets rendered into the bar
+struct BarStatus {
+    text: String,
+    tooltip: String,
+    class: String,
+    color: Option<&'static str>,
+}
+
+pub fn run_bar(config: &Config, format: &BarFormat, once: bool) {
+    let interval = Duration::from_secs(config.update_interval);
+    loop {
+        let status = match fetch_all_weather_features(config) {
+            Ok(features) => summarize_features(&features),
+            Err(e) => BarStatus {
+                text: "nwsd: error".to_string(),
+                tooltip: e,
+                class: "error".to_string(),
+                color: None,
+            },
+        };
+
+        println!("{}", render_status(&status, format));
+
+        if once {
+            break;
+        }
+        thread::sleep(interval);
+    }
+}
+
+fn summarize_features(features: &[Feature]) -> BarStatus {
+    let highest = features
+        .iter()
+        .map(|feature| &feature.properties.severity)
+        .max_by_key(|severity| get_level_for_severity(severity));
+
+    let severity = match highest {
+        Some(severity) => severity,
+        None => {
+            return BarStatus {
+                text: "".to_string(),
+                tooltip: "No active alerts".to_string(),
+                class: "none".to_string(),
+                color: None,
+            }
+        }
+    };
+
+    let tooltip = features
+        .iter()
+        .map(|feature| {
+            format!(
+                "{}: {}",
+                feature.properties.event, feature.properties.headline
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    BarStatus {
+        text: format!("{} ({})", severity, features.len()),
+        tooltip,
+        class: severity.to_string().to_lowercase(),
+        color: Some(get_hex_for_color(get_color_for_severity(severity))),
+    }
+}
+
+fn render_status(status: &BarStatus, format: &BarFormat) -> String {
+    match format {
+        BarFormat::Waybar => json!({
+            "text": status.text,
+            "tooltip": status.tooltip,
+            "class": status.class,
+            "alt": status.class,
+        })
+        .to_string(),
+        BarFormat::I3blocks => format!(
+            "{}\n{}\n{}",
+            status.text,
+            status.text,
+            status.color.unwrap_or("")
+        ),
+        BarFormat::Polybar => match status.color {
+            Some(color) => format!("%{{F{}}}{}%{{F-}}", color, status.text),
+            None => status.text.clone(),
+        },
+    }
+}
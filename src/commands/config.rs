@@ -0,0 +1,48 @@
+use crate::{daemon::config::LoadedConfig, print_fatal};
+
+/// Prints the effective config as TOML, with the file, environment variable or default each
+/// option came from
+pub fn show_config(loaded: &LoadedConfig) {
+    let table = match toml::Table::try_from(&loaded.config) {
+        Ok(table) => table,
+        Err(e) => {
+            print_fatal!("Could not serialize config: {}", e);
+            return;
+        }
+    };
+
+    for warning in &loaded.warnings {
+        println!("# Warning: {}", warning);
+    }
+    if loaded.files.is_empty() {
+        println!("# No config file found, showing the default config");
+    }
+
+    // Plain values have to come before any tables in TOML
+    let mut values = String::new();
+    let mut tables = String::new();
+    for (key, source) in &loaded.sources {
+        let value = match table.get(key) {
+            Some(value) => value.clone(),
+            None => {
+                values.push_str(&format!("# {} is not set ({})\n", key, source));
+                continue;
+            }
+        };
+        let serialized = match toml::to_string(&toml::Table::from_iter([(key.clone(), value)])) {
+            Ok(serialized) => serialized,
+            Err(e) => {
+                print_fatal!("Could not serialize {}: {}", key, e);
+                continue;
+            }
+        };
+
+        if serialized.starts_with('[') {
+            tables.push_str(&format!("\n# From {}\n{}", source, serialized));
+        } else {
+            values.push_str(&format!("{} # {}\n", serialized.trim_end(), source));
+        }
+    }
+
+    print!("{}{}", values, tables);
+}
@@ -0,0 +1,43 @@
+use crate::{
+    daemon::Config,
+    print_error, print_info,
+    weather::forecast::{fetch_current_conditions, fetch_forecast, fetch_point},
+};
+
+/// Prints the current conditions and upcoming forecast periods for every configured location
+pub fn print_forecast(config: &Config, hourly: bool, periods: usize, detail: bool) {
+    for location in config.get_locations() {
+        let point = match fetch_point(config, &location) {
+            Ok(point) => point,
+            Err(e) => {
+                print_error!("Could not look up {}: {}", location.name, e);
+                continue;
+            }
+        };
+
+        print_info!(
+            "Forecast for {} (grid {} {},{}):",
+            location.name,
+            point.grid_id,
+            point.grid_x,
+            point.grid_y
+        );
+
+        match fetch_current_conditions(config, &point) {
+            Ok(conditions) => println!("Now: {}", conditions),
+            Err(e) => print_error!("Could not fetch current conditions: {}", e),
+        }
+
+        match fetch_forecast(config, &point, hourly) {
+            Ok(forecast) => {
+                for period in forecast.iter().take(periods) {
+                    println!("{}", period);
+                    if detail && !period.detailed_forecast.is_empty() {
+                        println!("    {}", period.detailed_forecast);
+                    }
+                }
+            }
+            Err(e) => print_error!("Could not fetch forecast: {}", e),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::path::Path;
+
+use chrono::{DateTime, Local};
+
+use crate::{
+    daemon::{
+        state::{read_state, DaemonState},
+        Config,
+    },
+    print_done, print_error, print_info, print_warn,
+};
+
+/// Exit code when the daemon is running and polled recently
+pub const EXIT_HEALTHY: i32 = 0;
+/// Exit code when the daemon isn't running or hasn't reached the API within the threshold
+pub const EXIT_UNHEALTHY: i32 = 1;
+
+/// How many of the longest poll intervals can pass without a successful poll by default
+const STALE_POLL_CYCLES: u64 = 3;
+
+/// Reports on the running daemon from its state file. The daemon is unhealthy when it isn't
+/// running or its last successful poll is more than `max_age` seconds old. Returns the process
+/// exit code
+pub fn check_health(config: &Config, max_age: Option<u64>) -> i32 {
+    let state = match read_state(config) {
+        Ok(state) => state,
+        Err(e) => {
+            print_error!("{}", e);
+            return EXIT_UNHEALTHY;
+        }
+    };
+    let max_age = max_age
+        .unwrap_or(config.get_max_update_interval() * STALE_POLL_CYCLES)
+        .max(1);
+    let now = Local::now();
+
+    print_info!(
+        "Daemon {} running since {}",
+        state.pid,
+        format_time(state.started, now)
+    );
+    match state.last_poll {
+        Some(last_poll) => print_info!(
+            "Last poll {}, next in {}s",
+            format_time(last_poll, now),
+            state.poll_interval
+        ),
+        None => print_info!("Hasn't finished a poll yet"),
+    }
+    if state.consecutive_failures > 0 {
+        print_warn!(format!(
+            "{} polls in a row failed, the last with: {}",
+            state.consecutive_failures,
+            state.last_error.as_deref().unwrap_or("unknown error")
+        ));
+    }
+    match (&state.notification_server, &state.notification_error) {
+        (Some(server), _) => print_info!("Notification server: {}", server),
+        (None, Some(e)) => print_warn!("Notification server unavailable: {}", e),
+        (None, None) => print_warn!("Notification server unavailable"),
+    }
+
+    match get_problem(&state, max_age, now) {
+        Some(problem) => {
+            print_error!("Unhealthy: {}", problem);
+            EXIT_UNHEALTHY
+        }
+        None => {
+            print_done!("Healthy");
+            EXIT_HEALTHY
+        }
+    }
+}
+
+/// Why the daemon is unhealthy, a daemon that just started has `max_age` to finish its first poll
+fn get_problem(state: &DaemonState, max_age: u64, now: DateTime<Local>) -> Option<String> {
+    // The state file is left behind if the daemon is killed
+    if !Path::new("/proc").join(state.pid.to_string()).exists() {
+        return Some(format!("the daemon ({}) isn't running", state.pid));
+    }
+
+    let since = state.last_successful_poll.unwrap_or(state.started);
+    let age = now.signed_duration_since(since).num_seconds();
+    if age > max_age as i64 {
+        return Some(match state.last_successful_poll {
+            Some(_) => format!(
+                "the last successful poll was {}s ago, more than {}s",
+                age, max_age
+            ),
+            None => format!("no poll has succeeded in the {}s since starting", age),
+        });
+    }
+    None
+}
+
+fn format_time(time: DateTime<Local>, now: DateTime<Local>) -> String {
+    format!(
+        "{} ({}s ago)",
+        time.format("%a %-I:%M:%S %p"),
+        now.signed_duration_since(time).num_seconds()
+    )
+}
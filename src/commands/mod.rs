@@ -1,2 +1,8 @@
+pub mod alerts;
+pub mod bar;
+pub mod config;
+pub mod forecast;
+pub mod health;
+pub mod service;
 pub mod test;
 pub mod version;
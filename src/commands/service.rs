@@ -0,0 +1,86 @@
+use std::{fs, path::PathBuf};
+
+use crate::{daemon::Config, print_done, print_info};
+
+/// How many poll cycles can fail in a row before systemd's watchdog restarts the daemon
+const WATCHDOG_POLL_CYCLES: u64 = 3;
+
+/// Writes a systemd user unit that runs the daemon as a `Type=notify` service with a watchdog
+pub fn install_service(
+    config: &Config,
+    config_directory: Option<String>,
+    force: bool,
+) -> Result<(), String> {
+    let mut unit_path =
+        dirs::config_dir().ok_or("Could not find your config directory".to_string())?;
+    unit_path.push("systemd/user/nwsd.service");
+
+    if unit_path.exists() && !force {
+        return Err(format!(
+            "{} already exists, use --force to replace it",
+            unit_path.to_string_lossy()
+        ));
+    }
+
+    let executable = std::env::current_exe()
+        .map_err(|e| format!("Could not find the nwsd executable: {}", e))?;
+    let mut exec_start = format!("{} run", quote_argument(&executable.to_string_lossy()));
+    if let Some(config_directory) = config_directory {
+        // The service doesn't run in the current directory
+        let config_path =
+            fs::canonicalize(&config_directory).unwrap_or(PathBuf::from(config_directory));
+        exec_start.push_str(&format!(
+            " --config-directory {}",
+            quote_argument(&config_path.to_string_lossy())
+        ));
+    }
+
+    let unit = format!(
+        "[Unit]
+Description=National Weather Service alert daemon
+# Started with the desktop session and stopped with it, notifications need its display and D-Bus
+PartOf=graphical-session.target
+After=graphical-session.target
+
+[Service]
+Type=notify
+ExecStart={}
+ExecReload=/bin/kill -HUP $MAINPID
+Restart=on-failure
+# Pinged after every poll cycle that reached the API, restarts nwsd after {} failed cycles
+WatchdogSec={}
+
+[Install]
+WantedBy=graphical-session.target
+",
+        exec_start,
+        WATCHDOG_POLL_CYCLES,
+        get_watchdog_seconds(config)
+    );
+
+    if let Some(directory) = unit_path.parent() {
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("Could not create {}: {}", directory.to_string_lossy(), e))?;
+    }
+    fs::write(&unit_path, unit)
+        .map_err(|e| format!("Could not write {}: {}", unit_path.to_string_lossy(), e))?;
+
+    print_done!("Installed service at {}", unit_path.to_string_lossy());
+    print_info!("Start it now and at login with: systemctl --user daemon-reload && systemctl --user enable --now nwsd");
+    Ok(())
+}
+
+/// Long enough for a few of the longest poll cycles, and at least a minute for short intervals
+fn get_watchdog_seconds(config: &Config) -> u64 {
+    (config.get_max_update_interval() * WATCHDOG_POLL_CYCLES).max(60)
+}
+
+fn quote_argument(argument: &str) -> String {
+    match argument.contains(char::is_whitespace) {
+        true => format!(
+            "\"{}\"",
+            argument.replace('\\', "\\\\").replace('"', "\\\"")
+        ),
+        false => argument.to_string(),
+    }
+}
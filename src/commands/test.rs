@@ -1,12 +1,25 @@
 use crate::{
     daemon::Config,
-    print_done, print_info,
-    weather::weather::{generate_test_alert, send_notification, Severity},
+    print_done, print_fatal, print_info,
+    weather::{
+        fallback::send_alert,
+        weather::{generate_test_alert, Severity},
+    },
 };
 
 pub fn test_alert(config: &Config, severity: &Severity) {
     let alert = generate_test_alert(severity);
     print_info!("Generating test alert {:?}", &alert);
-    send_notification(&alert, &config);
+    match send_alert(&alert, None, config) {
+        // Keep running until the emergency alert is acknowledged
+        Ok(Some(handle)) => {
+            handle.join().ok();
+        }
+        Ok(None) => {}
+        Err(e) => {
+            print_fatal!(e);
+            std::process::exit(1);
+        }
+    }
     print_done!("Sent a test alert of severity {}", severity);
 }
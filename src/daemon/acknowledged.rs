@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Local};
+
+use crate::weather::weather::AlertProperties;
+
+/// How long an identity is kept after its alert ends, so a message that lingers in the API or a
+/// late update isn't notified again
+const GRACE_HOURS: i64 = 6;
+
+/// How long an identity is kept when its alert doesn't say when it ends
+const UNKNOWN_END_HOURS: i64 = 48;
+
+/// The most identities kept at once, the ones that would be forgotten first go when it is full
+const MAX_IDENTITIES: usize = 10_000;
+
+/// The identities of alerts that were already notified, see `AlertProperties::get_identities`.
+/// Each is forgotten a while after the alert it came from ends
+#[derive(Debug, Default)]
+pub struct AcknowledgedAlerts {
+    /// When each identity can be forgotten
+    forget_at: HashMap<String, DateTime<Local>>,
+    /// How many identities were forgotten since the daemon started
+    evicted: u64,
+}
+
+impl AcknowledgedAlerts {
+    pub fn contains(&self, identity: &str) -> bool {
+        self.forget_at.contains_key(identity)
+    }
+
+    /// Remembers every identity of an alert until it ends, an update that ends later keeps them
+    /// for longer
+    pub fn acknowledge(&mut self, alert_properties: &AlertProperties, now: DateTime<Local>) {
+        let forget_at = match alert_properties
+            .expires
+            .into_iter()
+            .chain(alert_properties.ends)
+            .max()
+        {
+            Some(end) => end.with_timezone(&Local) + Duration::hours(GRACE_HOURS),
+            None => now + Duration::hours(UNKNOWN_END_HOURS),
+        };
+
+        for identity in alert_properties.get_identities() {
+            let entry = self.forget_at.entry(identity).or_insert(forget_at);
+            *entry = (*entry).max(forget_at);
+        }
+
+        if self.forget_at.len() > MAX_IDENTITIES {
+            let mut by_age: Vec<(String, DateTime<Local>)> = self.forget_at.drain().collect();
+            by_age.sort_by_key(|(_, forget_at)| std::cmp::Reverse(*forget_at));
+            self.evicted += (by_age.len() - MAX_IDENTITIES) as u64;
+            self.forget_at = by_age.into_iter().take(MAX_IDENTITIES).collect();
+        }
+    }
+
+    /// Forgets the identities of alerts that ended more than the grace period ago, returns how
+    /// many were forgotten
+    pub fn evict_expired(&mut self, now: DateTime<Local>) -> usize {
+        let before = self.forget_at.len();
+        self.forget_at.retain(|_, forget_at| *forget_at > now);
+        let evicted = before - self.forget_at.len();
+        self.evicted += evicted as u64;
+        evicted
+    }
+
+    /// How many identities are remembered
+    pub fn len(&self) -> usize {
+        self.forget_at.len()
+    }
+
+    /// How many identities were forgotten since the daemon started
+    pub fn get_evicted_count(&self) -> u64 {
+        self.evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weather::weather::{generate_test_alert, Severity};
+
+    fn alert(id: &str, ends: Option<DateTime<Local>>) -> AlertProperties {
+        AlertProperties {
+            id: id.to_string(),
+            ends: ends.map(|ends| ends.fixed_offset()),
+            ..generate_test_alert(&Severity::Minor)
+        }
+    }
+
+    #[test]
+    fn kept_for_the_grace_period_after_the_alert_ends() {
+        let now = Local::now();
+        let ends = now + Duration::hours(1);
+        let mut acknowledged = AcknowledgedAlerts::default();
+        acknowledged.acknowledge(&alert("1", Some(ends)), now);
+
+        let grace_end = ends + Duration::hours(GRACE_HOURS);
+        assert_eq!(
+            acknowledged.evict_expired(grace_end - Duration::minutes(1)),
+            0
+        );
+        assert!(acknowledged.contains("1"));
+
+        assert_eq!(acknowledged.evict_expired(grace_end), 1);
+        assert!(!acknowledged.contains("1"));
+        assert_eq!(acknowledged.get_evicted_count(), 1);
+    }
+
+    #[test]
+    fn later_update_keeps_it_longer() {
+        let now = Local::now();
+        let mut acknowledged = AcknowledgedAlerts::default();
+        acknowledged.acknowledge(&alert("1", Some(now + Duration::hours(1))), now);
+        acknowledged.acknowledge(&alert("1", Some(now + Duration::hours(10))), now);
+        // An older message arriving late doesn't shorten it again
+        acknowledged.acknowledge(&alert("1", Some(now + Duration::hours(1))), now);
+
+        acknowledged.evict_expired(now + Duration::hours(10));
+        assert!(acknowledged.contains("1"));
+    }
+
+    #[test]
+    fn unknown_end_is_kept_for_a_while() {
+        let now = Local::now();
+        let mut acknowledged = AcknowledgedAlerts::default();
+        acknowledged.acknowledge(&alert("1", None), now);
+
+        acknowledged.evict_expired(now + Duration::hours(UNKNOWN_END_HOURS - 1));
+        assert!(acknowledged.contains("1"));
+        acknowledged.evict_expired(now + Duration::hours(UNKNOWN_END_HOURS));
+        assert!(!acknowledged.contains("1"));
+    }
+
+    #[test]
+    fn capped_by_forgetting_the_soonest_to_end() {
+        let now = Local::now();
+        let mut acknowledged = AcknowledgedAlerts::default();
+        for i in 0..MAX_IDENTITIES {
+            let ends = now + Duration::minutes(i as i64 + 1);
+            acknowledged.acknowledge(&alert(&i.to_string(), Some(ends)), now);
+        }
+        assert_eq!(acknowledged.len(), MAX_IDENTITIES);
+        assert_eq!(acknowledged.get_evicted_count(), 0);
+
+        acknowledged.acknowledge(&alert("last", Some(now + Duration::days(1))), now);
+
+        assert_eq!(acknowledged.len(), MAX_IDENTITIES);
+        assert_eq!(acknowledged.get_evicted_count(), 1);
+        assert!(!acknowledged.contains("0"));
+        assert!(acknowledged.contains("1"));
+        assert!(acknowledged.contains("last"));
+    }
+}
@@ -1,16 +1,26 @@
 use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
     fs::File,
     io::{ErrorKind, Read, Write},
     path::{Path, PathBuf},
 };
 
-use crate::{daemon::Config, print_done, print_fatal, print_info};
+use crate::{
+    daemon::{
+        migrate::{get_config_version, upgrade_table},
+        validate::{find_unknown_keys, get_config_keys, validate_config},
+        Config, Location,
+    },
+    print_done, print_fatal, print_info,
+    weather::{fallback::FallbackSink, sound::default_siren_events},
+};
 
 fn config_exists(path: &Path) -> bool {
     path.exists()
 }
 
-fn check_direcotry(directory_string: Option<String>) -> PathBuf {
+pub fn check_direcotry(directory_string: Option<String>) -> PathBuf {
     match directory_string {
         Some(a) => PathBuf::from(a),
         None => {
@@ -21,48 +31,210 @@ fn check_direcotry(directory_string: Option<String>) -> PathBuf {
     }
 }
 
-pub fn load_config_from_file(directory_string: Option<String>) -> Option<Config> {
-    let final_directory = check_direcotry(directory_string);
+/// The current layout version of the config file, bumped when options are renamed or change
+/// meaning so `nwsd config migrate` can upgrade older files
+pub const CONFIG_VERSION: u32 = 2;
+
+/// The annotated config written by `nwsd init-config`, its values are the defaults
+pub const CONFIG_TEMPLATE: &str = include_str!("config_template.toml");
 
-    print_info!("Loading config from: {}", final_directory.to_string_lossy());
+/// Config shared by every user, the user's config is layered over it
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/nwsd.toml";
 
-    if !config_exists(final_directory.as_path()) {
-        return None;
+/// Environment variables starting with this override a single option, e.g.
+/// `NWSD_UPDATE_INTERVAL=120`
+const ENVIRONMENT_PREFIX: &str = "NWSD_";
+
+/// Where the effective value of an option came from
+#[derive(Debug, Clone)]
+pub enum ConfigSource {
+    Default,
+    File(PathBuf),
+    Environment(String),
+}
+
+impl Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::File(path) => write!(f, "{}", path.to_string_lossy()),
+            ConfigSource::Environment(variable) => write!(f, "${}", variable),
+        }
     }
+}
+
+/// A config that passed validation, along with anything suspicious found in it
+#[derive(Debug)]
+pub struct LoadedConfig {
+    pub config: Config,
+    pub warnings: Vec<String>,
+    /// Where each top level option came from
+    pub sources: BTreeMap<String, ConfigSource>,
+    /// The config files that were found, in the order they were layered
+    pub files: Vec<PathBuf>,
+}
+
+/// Loads the config by layering the system config, the user's config and `NWSD_*` environment
+/// variables over the defaults. Each layer replaces the top level options it sets. Returns every
+/// problem that makes the config unusable
+pub fn load_config(directory_string: Option<String>) -> Result<LoadedConfig, Vec<String>> {
+    let mut layers: Vec<(toml::Table, ConfigSource)> = vec![];
+    let mut files = vec![];
+    let mut warnings = vec![];
+    let mut errors = vec![];
+
+    for path in [
+        PathBuf::from(SYSTEM_CONFIG_PATH),
+        check_direcotry(directory_string),
+    ] {
+        if !config_exists(&path) {
+            continue;
+        }
+        match read_config_file(&path) {
+            Ok(mut table) => {
+                // Older files still load, with their options moved to where they are now
+                let version = match get_config_version(&table) {
+                    Ok(version) => version,
+                    Err(error) => {
+                        errors.push(format!("{}: {}", path.to_string_lossy(), error));
+                        continue;
+                    }
+                };
+                let original = table.clone();
+                upgrade_table(&mut table, version);
+                if table != original {
+                    warnings.push(format!(
+                        "{}: The config is version {}, run nwsd config migrate to upgrade it",
+                        path.to_string_lossy(),
+                        version
+                    ));
+                }
 
-    let read_text = match File::open(final_directory) {
-        Ok(mut file) => {
-            let mut read_config: String = "".to_string();
-            match file.read_to_string(&mut read_config) {
-                Ok(_) => {}
-                Err(_) => {
-                    print_fatal!("Could not open config for reading, reverting to default config.");
-                    return None;
+                for key in find_unknown_keys(&table) {
+                    warnings.push(format!(
+                        "{}: Unknown config option {:?} will be ignored",
+                        path.to_string_lossy(),
+                        key
+                    ));
                 }
-            };
-            read_config
+                layers.push((table, ConfigSource::File(path.clone())));
+                files.push(path);
+            }
+            Err(error) => errors.push(format!("{}: {}", path.to_string_lossy(), error)),
+        }
+    }
+
+    let known_keys = get_config_keys();
+    let mut environment: Vec<(String, String)> = std::env::vars()
+        .filter(|(variable, _)| variable.starts_with(ENVIRONMENT_PREFIX))
+        .collect();
+    environment.sort();
+    for (variable, raw) in environment {
+        let key = variable[ENVIRONMENT_PREFIX.len()..].to_lowercase();
+        if !known_keys.contains(&key) {
+            warnings.push(format!(
+                "${}: Unknown config option {:?} will be ignored",
+                variable, key
+            ));
+            continue;
         }
-        Err(_) => {
-            print_fatal!("Could not open config for reading, reverting to default config.");
-            return None;
+        match parse_environment_value(&key, &raw) {
+            Ok(value) => layers.push((
+                toml::Table::from_iter([(key, value)]),
+                ConfigSource::Environment(variable),
+            )),
+            Err(error) => errors.push(format!("${}: {}", variable, error)),
         }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut sources: BTreeMap<String, ConfigSource> = known_keys
+        .into_iter()
+        .map(|key| (key, ConfigSource::Default))
+        .collect();
+    let mut merged = toml::Table::new();
+    for (table, source) in layers {
+        for (key, value) in table {
+            if let Some(key_source) = sources.get_mut(&key) {
+                *key_source = source.clone();
+            }
+            merged.insert(key, value);
+        }
+    }
+
+    let config: Config = merged.try_into().map_err(|e| vec![e.to_string()])?;
+
+    let issues = validate_config(&config);
+    if !issues.errors.is_empty() {
+        return Err(issues.errors);
+    }
+    warnings.extend(issues.warnings);
+
+    Ok(LoadedConfig {
+        config,
+        warnings,
+        sources,
+        files,
+    })
+}
+
+/// Reads a config file into a table, checking the options it sets on their own first since those
+/// errors include the line and column of the problem
+fn read_config_file(path: &Path) -> Result<toml::Table, String> {
+    let mut read_text: String = "".to_string();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut read_text))
+        .map_err(|e| format!("Could not open config for reading: {}", e))?;
+
+    toml::from_str::<Config>(&read_text).map_err(|e| e.to_string())?;
+    toml::from_str(&read_text).map_err(|e| e.to_string())
+}
+
+/// Environment values are read as TOML so numbers, booleans and lists work, values that aren't
+/// valid TOML for the option (like `07:00` or an unquoted user agent) are taken as a string
+fn parse_environment_value(key: &str, raw: &str) -> Result<toml::Value, String> {
+    let check = |value: &toml::Value| {
+        toml::Table::from_iter([(key.to_string(), value.clone())]).try_into::<Config>()
+    };
+
+    let typed = format!("value = {}", raw)
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"));
+    let typed_error = match typed {
+        Some(value) => match check(&value) {
+            Ok(_) => return Ok(value),
+            Err(e) => Some(e.to_string()),
+        },
+        None => None,
     };
-    Some(toml::from_str(read_text.as_str()).unwrap_or_default())
+
+    let string = toml::Value::String(raw.to_string());
+    match check(&string) {
+        Ok(_) => Ok(string),
+        Err(e) => Err(typed_error.unwrap_or_else(|| e.to_string())),
+    }
 }
 
-pub fn create_default_config(directory_string: Option<String>) {
+/// Writes the annotated config template, refusing to replace an existing config unless `force`
+/// is set
+pub fn create_default_config(directory_string: Option<String>, force: bool) {
     let final_directory = check_direcotry(directory_string);
+    if config_exists(&final_directory) && !force {
+        print_fatal!(
+            "A config file already exists at {}. Run nwsd config migrate to upgrade it, or use --force to replace it with the default config.",
+            final_directory.to_string_lossy()
+        );
+        std::process::exit(1);
+    }
+
     print_info!(
         "Attempting to generate default config at {}",
         final_directory.to_string_lossy()
     );
-    let default_config = Config::default();
-    let serialized = if let Ok(value) = toml::to_string(&default_config) {
-        value
-    } else {
-        print_fatal!("Could not serialize default config. Please make an issue.");
-        return;
-    };
     let config_file = File::create(&final_directory);
     let mut config = match config_file {
         Ok(file) => file,
@@ -72,22 +244,23 @@ pub fn create_default_config(directory_string: Option<String>) {
                     "Looks like you don't have permission to write to {}.",
                     &final_directory.to_string_lossy()
                 );
-                return;
+                std::process::exit(1);
             }
             _ => {
                 print_fatal!("Failed to create config file: {}", error);
-                return;
+                std::process::exit(1);
             }
         },
     };
 
-    config.write_all(serialized.as_bytes()).unwrap_or_else(|_| {
-        print_fatal!(
-            "Could not write serialized output to file {}",
-            &final_directory.to_string_lossy()
-        );
-        return;
-    });
+    config
+        .write_all(CONFIG_TEMPLATE.as_bytes())
+        .unwrap_or_else(|_| {
+            print_fatal!(
+                "Could not write config to file {}",
+                &final_directory.to_string_lossy()
+            );
+        });
     print_done!(
         "Created config file at {}",
         &final_directory.to_string_lossy()
@@ -97,12 +270,124 @@ pub fn create_default_config(directory_string: Option<String>) {
 impl Default for Config {
     fn default() -> Self {
         Config {
+            config_version: CONFIG_VERSION,
             update_interval: 300,
-            lat: 36.974117,
-            lon: -122.030792,
             detailed_notification: false,
             notification_icon_path: None,
             user_agent: "nwsd notification app (https://github.com/Camerooooon/nwsd)".to_string(),
+            locations: vec![Location {
+                name: "Home".to_string(),
+                lat: 36.974117,
+                lon: -122.030792,
+            }],
+            morning_forecast: None,
+            forecast_rules: Vec::new(),
+            require_inside_polygon: false,
+            nearby_radius_miles: 0.0,
+            alert_filters: Vec::new(),
+            onset_reminder_minutes: 60,
+            extreme_alert_window: true,
+            extreme_alert_tone: false,
+            severity_sounds: HashMap::new(),
+            event_sounds: HashMap::new(),
+            siren_events: default_siren_events(),
+            siren_repeats: 1,
+            speech: None,
+            adaptive_polling: None,
+            metrics_address: None,
+            state_file: None,
+            notification_fallback: FallbackSink::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Every location to check, starting with the primary location
+    pub fn get_locations(&self) -> Vec<Location> {
+        self.locations.clone()
+    }
+
+    /// The longest the daemon can wait between polls
+    pub fn get_max_update_interval(&self) -> u64 {
+        match &self.adaptive_polling {
+            Some(adaptive) => adaptive.max_interval,
+            None => self.update_interval,
+        }
+    }
+}
+
+/// Reads and writes `morning_forecast` as an HH:MM string, so a malformed time is rejected when
+/// the config is loaded
+pub mod hour_minute {
+    use chrono::NaiveTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    const FORMAT: &str = "%H:%M";
+
+    pub fn serialize<S: Serializer>(
+        time: &Option<NaiveTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match time {
+            Some(time) => serializer.serialize_str(&time.format(FORMAT).to_string()),
+            None => serializer.serialize_none(),
         }
     }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<NaiveTime>, D::Error> {
+        let Some(time) = Option::<String>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
+        NaiveTime::parse_from_str(&time, FORMAT)
+            .map(Some)
+            .map_err(|_| {
+                serde::de::Error::custom(format!(
+                    "morning_forecast {:?} is not a time, expected HH:MM such as \"07:00\"",
+                    time
+                ))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environment_values_are_typed_when_the_option_accepts_them() {
+        assert_eq!(
+            parse_environment_value("update_interval", "120"),
+            Ok(toml::Value::Integer(120))
+        );
+        assert_eq!(
+            parse_environment_value("extreme_alert_window", "true"),
+            Ok(toml::Value::Boolean(true))
+        );
+        assert_eq!(
+            parse_environment_value("siren_events", "[\"TornadoWarning\"]"),
+            Ok(toml::Value::Array(vec![toml::Value::String(
+                "TornadoWarning".to_string()
+            )]))
+        );
+    }
+
+    #[test]
+    fn environment_values_fall_back_to_a_string() {
+        // Valid TOML, but a time rather than the string the option expects
+        assert_eq!(
+            parse_environment_value("morning_forecast", "07:00"),
+            Ok(toml::Value::String("07:00".to_string()))
+        );
+        assert_eq!(
+            parse_environment_value("user_agent", "120"),
+            Ok(toml::Value::String("120".to_string()))
+        );
+        assert_eq!(
+            parse_environment_value("user_agent", "nwsd (me@example.com)"),
+            Ok(toml::Value::String("nwsd (me@example.com)".to_string()))
+        );
+        assert!(parse_environment_value("update_interval", "soon").is_err());
+    }
 }
@@ -1,10 +1,21 @@
+use std::sync::Arc;
+
+use crate::daemon::acknowledged::AcknowledgedAlerts;
+use crate::daemon::state::DaemonState;
 use crate::daemon::Config;
 use crate::daemon::Daemon;
 
-pub fn init_daemon(config: Config, debug: bool) -> Daemon {
+pub fn init_daemon(config: Config, config_directory: Option<String>, debug: bool) -> Daemon {
     Daemon {
-        config,
+        poll_interval: config.update_interval,
+        state: DaemonState::new(config.update_interval),
+        config: Arc::new(config),
+        config_directory,
         debug,
-        acknowledged_alerts: Vec::new(),
+        acknowledged_alerts: AcknowledgedAlerts::default(),
+        last_morning_forecast: None,
+        pending_onsets: Vec::new(),
+        spc_risk: None,
+        last_spc_check: None,
     }
 }
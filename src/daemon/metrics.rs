@@ -0,0 +1,234 @@
+use std::{sync::LazyLock, time::Duration};
+
+use prometheus::{
+    Encoder, Gauge, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    Opts, Registry, TextEncoder,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+use crate::{print_error, print_info};
+
+/// Every metric the daemon keeps. They are recorded whether or not `metrics_address` is set, it
+/// only controls whether they are served
+pub struct Metrics {
+    registry: Registry,
+    pub polls: IntCounter,
+    /// By `kind`, see `FetchErrorKind`
+    pub fetch_errors: IntCounterVec,
+    /// By `sink` and `severity`
+    pub notifications: IntCounterVec,
+    /// By `severity` and `event`
+    pub active_alerts: IntGaugeVec,
+    /// Unix time of the last poll that reached the API for at least one location
+    pub last_successful_poll: Gauge,
+    /// Seconds each request took, by `endpoint`
+    pub request_duration: HistogramVec,
+    pub acknowledged_alerts: IntGauge,
+    pub forgotten_alerts: IntCounter,
+}
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Why a request failed
+#[derive(Debug, Clone, Copy)]
+pub enum FetchErrorKind {
+    Timeout,
+    Connect,
+    Request,
+    /// The API answered with an error status
+    Status,
+    /// The response couldn't be read or parsed
+    Body,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let metrics = Metrics {
+            polls: IntCounter::new("nwsd_polls_total", "Polls started").unwrap(),
+            fetch_errors: IntCounterVec::new(
+                Opts::new("nwsd_fetch_errors_total", "Failed requests by kind"),
+                &["kind"],
+            )
+            .unwrap(),
+            notifications: IntCounterVec::new(
+                Opts::new(
+                    "nwsd_notifications_total",
+                    "Notifications sent by sink and severity",
+                ),
+                &["sink", "severity"],
+            )
+            .unwrap(),
+            active_alerts: IntGaugeVec::new(
+                Opts::new(
+                    "nwsd_active_alerts",
+                    "Active alerts for the configured locations by severity and event",
+                ),
+                &["severity", "event"],
+            )
+            .unwrap(),
+            last_successful_poll: Gauge::new(
+                "nwsd_last_successful_poll_timestamp_seconds",
+                "When the API was last reached",
+            )
+            .unwrap(),
+            request_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "nwsd_request_duration_seconds",
+                    "How long requests to the weather APIs took by endpoint",
+                )
+                .buckets(vec![0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0]),
+                &["endpoint"],
+            )
+            .unwrap(),
+            acknowledged_alerts: IntGauge::new(
+                "nwsd_acknowledged_alerts",
+                "Alert identities remembered as already notified",
+            )
+            .unwrap(),
+            forgotten_alerts: IntCounter::new(
+                "nwsd_forgotten_alerts_total",
+                "Alert identities forgotten after their alert ended",
+            )
+            .unwrap(),
+            registry,
+        };
+
+        for collector in [
+            Box::new(metrics.polls.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(metrics.fetch_errors.clone()),
+            Box::new(metrics.notifications.clone()),
+            Box::new(metrics.active_alerts.clone()),
+            Box::new(metrics.last_successful_poll.clone()),
+            Box::new(metrics.request_duration.clone()),
+            Box::new(metrics.acknowledged_alerts.clone()),
+            Box::new(metrics.forgotten_alerts.clone()),
+        ] {
+            if let Err(e) = metrics.registry.register(collector) {
+                print_error!("Could not register metric: {}", e);
+            }
+        }
+        metrics
+    }
+
+    pub fn record_fetch_error(&self, kind: FetchErrorKind) {
+        self.fetch_errors
+            .with_label_values(&[kind.get_label()])
+            .inc();
+    }
+
+    pub fn record_notification(&self, sink: &str, severity: &str) {
+        self.notifications
+            .with_label_values(&[sink, severity])
+            .inc();
+    }
+
+    /// The metrics in the Prometheus text format
+    pub fn encode(&self) -> Result<String, String> {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|e| e.to_string())?;
+        String::from_utf8(buffer).map_err(|e| e.to_string())
+    }
+}
+
+impl FetchErrorKind {
+    pub fn get_label(&self) -> &'static str {
+        match self {
+            FetchErrorKind::Timeout => "timeout",
+            FetchErrorKind::Connect => "connect",
+            FetchErrorKind::Request => "request",
+            FetchErrorKind::Status => "status",
+            FetchErrorKind::Body => "body",
+        }
+    }
+}
+
+/// Names the API endpoint a URL belongs to, so request metrics don't get a label per location
+pub fn get_endpoint_label(url: &str) -> &'static str {
+    let path = url.split("://").nth(1).unwrap_or(url);
+    if path.starts_with("www.spc.noaa.gov") {
+        return "spc_outlook";
+    }
+    match path.split('/').nth(1) {
+        Some("alerts") => "alerts",
+        Some("points") => "points",
+        Some("gridpoints") => "forecast",
+        Some("stations") => "observations",
+        _ => "other",
+    }
+}
+
+/// How long a client gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the metrics at `/metrics` on `address` in the background. The address is only read at
+/// startup
+pub async fn serve_metrics(address: &str) {
+    let listener = match TcpListener::bind(address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            print_error!("Could not serve metrics on {}: {}", address, e);
+            return;
+        }
+    };
+    print_info!("Serving metrics at http://{}/metrics", address);
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(async move {
+                        if let Err(e) = answer_request(stream).await {
+                            print_error!("Could not answer metrics request: {}", e);
+                        }
+                    });
+                }
+                Err(e) => print_error!("Could not accept metrics connection: {}", e),
+            }
+        }
+    });
+}
+
+async fn answer_request(mut stream: TcpStream) -> Result<(), String> {
+    // Only the request line matters, the rest of the request is ignored
+    let mut buffer = [0; 1024];
+    let read = timeout(REQUEST_TIMEOUT, stream.read(&mut buffer))
+        .await
+        .map_err(|_| "Timed out reading the request".to_string())?
+        .map_err(|e| e.to_string())?;
+    let request = String::from_utf8_lossy(&buffer[..read]);
+    let mut request_line = request.lines().next().unwrap_or("").split_whitespace();
+
+    let (status, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => match METRICS.encode() {
+            Ok(metrics) => ("200 OK", metrics),
+            Err(e) => ("500 Internal Server Error", format!("{}\n", e)),
+        },
+        (Some("GET"), _) => (
+            "404 Not Found",
+            "Metrics are served at /metrics\n".to_string(),
+        ),
+        _ => (
+            "405 Method Not Allowed",
+            "Only GET is supported\n".to_string(),
+        ),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream
+        .write_all(response.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    stream.shutdown().await.map_err(|e| e.to_string())
+}
@@ -0,0 +1,223 @@
+use std::fs;
+
+use crate::{
+    daemon::{
+        config::{check_direcotry, CONFIG_TEMPLATE, CONFIG_VERSION},
+        Config,
+    },
+    print_done,
+};
+
+/// Upgrades a config table by one version, the step at index `n` upgrades version `n + 1`
+type Migration = fn(&mut toml::Table);
+
+const MIGRATIONS: [Migration; (CONFIG_VERSION - 1) as usize] = [migrate_v1_to_v2];
+
+/// Version 1 files were written before `config_version` existed, with a single location in the
+/// top level `lat` and `lon`. Version 2 lists every location in `[[locations]]`, the old one goes
+/// first as "Home"
+fn migrate_v1_to_v2(table: &mut toml::Table) {
+    let (lat, lon) = (table.remove("lat"), table.remove("lon"));
+    if lat.is_none() && lon.is_none() {
+        return;
+    }
+
+    let default = &Config::default().locations[0];
+    let home = toml::Table::from_iter([
+        (
+            "name".to_string(),
+            toml::Value::String(default.name.clone()),
+        ),
+        (
+            "lat".to_string(),
+            lat.unwrap_or(toml::Value::Float(default.lat)),
+        ),
+        (
+            "lon".to_string(),
+            lon.unwrap_or(toml::Value::Float(default.lon)),
+        ),
+    ]);
+    let mut locations = match table.remove("locations") {
+        Some(toml::Value::Array(locations)) => locations,
+        _ => vec![],
+    };
+    locations.insert(0, toml::Value::Table(home));
+    table.insert("locations".to_string(), toml::Value::Array(locations));
+}
+
+/// The layout version of a config table, files without `config_version` are version 1
+pub fn get_config_version(table: &toml::Table) -> Result<u32, String> {
+    match table.get("config_version") {
+        None => Ok(1),
+        Some(toml::Value::Integer(version)) if *version >= 1 => Ok(*version as u32),
+        Some(version) => Err(format!(
+            "config_version {} is not a valid version, it has to be at least 1",
+            version
+        )),
+    }
+}
+
+/// Upgrades a config table from `version` to the current version in place, tables that are
+/// already current are left alone
+pub fn upgrade_table(table: &mut toml::Table, version: u32) {
+    for migration in MIGRATIONS.iter().skip(version.saturating_sub(1) as usize) {
+        migration(table);
+    }
+}
+
+/// Upgrades the config file to the current version, keeping every value that is set in it and
+/// laying it out like the annotated template. The old file is kept next to it with a `.bak`
+/// extension
+pub fn migrate_config(directory_string: Option<String>) -> Result<(), String> {
+    let path = check_direcotry(directory_string);
+    if !path.exists() {
+        return Err(format!(
+            "There is no config file at {}, run nwsd init-config to create one",
+            path.to_string_lossy()
+        ));
+    }
+
+    let text = fs::read_to_string(&path)
+        .map_err(|e| format!("Could not read {}: {}", path.to_string_lossy(), e))?;
+    let mut table: toml::Table =
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.to_string_lossy(), e))?;
+
+    let version = get_config_version(&table)?;
+
+    if version > CONFIG_VERSION {
+        return Err(format!(
+            "{} is version {}, which is newer than this version of nwsd understands ({})",
+            path.to_string_lossy(),
+            version,
+            CONFIG_VERSION
+        ));
+    }
+    if version == CONFIG_VERSION {
+        print_done!(
+            "{} is already at the current version {}",
+            path.to_string_lossy(),
+            CONFIG_VERSION
+        );
+        return Ok(());
+    }
+
+    upgrade_table(&mut table, version);
+    table.remove("config_version");
+
+    let migrated = apply_to_template(&table)?;
+    toml::from_str::<Config>(&migrated).map_err(|e| {
+        format!(
+            "The migrated config doesn't load, please make an issue: {}",
+            e
+        )
+    })?;
+
+    let mut backup = path.clone().into_os_string();
+    backup.push(".bak");
+    fs::copy(&path, &backup).map_err(|e| {
+        format!(
+            "Could not back up the config to {}: {}",
+            backup.to_string_lossy(),
+            e
+        )
+    })?;
+    fs::write(&path, migrated)
+        .map_err(|e| format!("Could not write {}: {}", path.to_string_lossy(), e))?;
+
+    print_done!(
+        "Migrated {} from version {} to {}, the old config was saved to {}",
+        path.to_string_lossy(),
+        version,
+        CONFIG_VERSION,
+        backup.to_string_lossy()
+    );
+    Ok(())
+}
+
+/// Sets each value in the annotated template, options the template doesn't have are added to the
+/// top level of the document so they don't end up inside its tables
+fn apply_to_template(values: &toml::Table) -> Result<String, String> {
+    let mut document: toml_edit::DocumentMut = CONFIG_TEMPLATE
+        .parse()
+        .map_err(|e| format!("Could not parse the config template: {}", e))?;
+
+    for (key, value) in values {
+        // Round trip the value through text so the template keeps its comments
+        let text = toml::to_string(&toml::Table::from_iter([(key.clone(), value.clone())]))
+            .map_err(|e| format!("Could not serialize {}: {}", key, e))?;
+        let mut parsed: toml_edit::DocumentMut = text
+            .parse()
+            .map_err(|e| format!("Could not serialize {}: {}", key, e))?;
+        if let Some(item) = parsed.remove(key) {
+            document[key.as_str()] = item;
+        }
+    }
+
+    Ok(document.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+
+    use super::*;
+    use crate::daemon::validate::{find_unknown_keys, validate_config};
+
+    /// A config written by `init-config` before `config_version` existed
+    const V1_CONFIG: &str = r#"
+update_interval = 120
+lat = 35.4676
+lon = -97.5164
+detailed_notification = true
+notification_icon_path = "/x.svg"
+user_agent = "nwsd test"
+morning_forecast = "07:30"
+
+[[locations]]
+name = "Work"
+lat = 35.2226
+lon = -97.4395
+"#;
+
+    #[test]
+    fn v1_config_migrates_to_a_valid_v2_config() {
+        let mut table: toml::Table = toml::from_str(V1_CONFIG).unwrap();
+        let version = get_config_version(&table).unwrap();
+        assert_eq!(version, 1);
+
+        upgrade_table(&mut table, version);
+        assert!(!table.contains_key("lat") && !table.contains_key("lon"));
+
+        let migrated = apply_to_template(&table).unwrap();
+        // Options the template only has commented out have to stay at the top level
+        assert!(find_unknown_keys(&toml::from_str(&migrated).unwrap()).is_empty());
+        let config: Config = toml::from_str(&migrated).unwrap();
+        assert_eq!(config.config_version, CONFIG_VERSION);
+        assert!(validate_config(&config).errors.is_empty());
+
+        let locations: Vec<(&str, f64, f64)> = config
+            .locations
+            .iter()
+            .map(|location| (location.name.as_str(), location.lat, location.lon))
+            .collect();
+        assert_eq!(
+            locations,
+            [("Home", 35.4676, -97.5164), ("Work", 35.2226, -97.4395)]
+        );
+        assert_eq!(config.update_interval, 120);
+        assert!(config.detailed_notification);
+        assert_eq!(config.user_agent, "nwsd test");
+        assert_eq!(config.notification_icon_path.as_deref(), Some("/x.svg"));
+        assert_eq!(config.morning_forecast, NaiveTime::from_hms_opt(7, 30, 0));
+    }
+
+    #[test]
+    fn current_config_is_left_alone() {
+        let mut table: toml::Table = toml::from_str(CONFIG_TEMPLATE).unwrap();
+        let original = table.clone();
+        let version = get_config_version(&table).unwrap();
+        assert_eq!(version, CONFIG_VERSION);
+        upgrade_table(&mut table, version);
+        assert_eq!(table, original);
+    }
+}
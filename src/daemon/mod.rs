@@ -1,24 +1,117 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveTime};
 use serde::{Deserialize, Serialize};
 
+use crate::daemon::{
+    acknowledged::AcknowledgedAlerts, schedule::AdaptivePolling, state::DaemonState,
+};
+use crate::weather::{
+    fallback::FallbackSink,
+    filters::AlertFilter,
+    geometry::Proximity,
+    outlook::SpcRisk,
+    rules::ForecastRule,
+    sound::Sound,
+    speech::SpeechConfig,
+    weather::{Event, Feature, Severity},
+};
+
+pub mod acknowledged;
 pub mod config;
 pub mod init;
+pub mod metrics;
+pub mod migrate;
+pub mod poll;
+pub mod reload;
 pub mod run;
+pub mod schedule;
+pub mod signals;
+pub mod state;
+pub mod systemd;
+pub mod validate;
 
 /// An instance of a running daemon. Contains state for that specific daemon
 #[derive(Debug)]
 pub struct Daemon {
-    pub config: Config,
+    /// Shared with the fetches running in the background, replaced as a whole on reload
+    pub config: Arc<Config>,
+    /// Where the config was loaded from, used to reload it
+    pub config_directory: Option<String>,
     pub debug: bool,
-    pub acknowledged_alerts: Vec<String>,
+    pub acknowledged_alerts: AcknowledgedAlerts,
+    pub last_morning_forecast: Option<NaiveDate>,
+    pub pending_onsets: Vec<PendingOnset>,
+    /// Seconds until the next poll, see `schedule`
+    pub poll_interval: u64,
+    pub spc_risk: Option<SpcRisk>,
+    pub last_spc_check: Option<DateTime<Local>>,
+    /// Written to the state file for `nwsd health`
+    pub state: DaemonState,
+}
+
+/// An alert issued well ahead of its onset that gets notified again once it begins
+#[derive(Debug)]
+pub struct PendingOnset {
+    pub onset: DateTime<FixedOffset>,
+    pub feature: Feature,
+    pub proximity: Option<Proximity>,
 }
 
-/// Config for a daemon
+/// Config for a daemon. Every option is optional, missing ones take their value from
+/// `Config::default()`
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
+    /// The layout version of the config file, see `migrate`
+    pub config_version: u32,
     pub update_interval: u64,
-    pub lat: f64,
-    pub lon: f64,
     pub detailed_notification: bool,
     pub notification_icon_path: Option<String>,
     pub user_agent: String,
+    /// The named locations to check, the first is the primary location
+    pub locations: Vec<Location>,
+    /// Local time (HH:MM) after which a forecast notification is sent once a day
+    #[serde(with = "config::hour_minute")]
+    pub morning_forecast: Option<NaiveTime>,
+    /// Personal triggers checked against the hourly forecast
+    pub forecast_rules: Vec<ForecastRule>,
+    /// Only notify for storm-based alerts when a location is inside the warned polygon
+    pub require_inside_polygon: bool,
+    /// With `require_inside_polygon`, still notify for polygons within this many miles
+    pub nearby_radius_miles: f64,
+    /// Conditions an alert has to meet to be notified, see `AlertFilter`
+    pub alert_filters: Vec<AlertFilter>,
+    /// Alerts whose onset is more than this many minutes away are notified again at onset, 0
+    /// disables onset reminders
+    pub onset_reminder_minutes: i64,
+    /// Show Extreme alerts in an always on top window that has to be acknowledged
+    pub extreme_alert_window: bool,
+    /// Play an attention tone when showing the Extreme alert window
+    pub extreme_alert_tone: bool,
+    /// Sounds played for alerts of a severity
+    pub severity_sounds: HashMap<Severity, Sound>,
+    /// Sounds played for alerts of an event, these take priority over `severity_sounds`
+    pub event_sounds: HashMap<Event, Sound>,
+    /// Events whose sound is repeated `siren_repeats` times
+    pub siren_events: Vec<Event>,
+    pub siren_repeats: u32,
+    /// Read alerts aloud with a local speech engine
+    pub speech: Option<SpeechConfig>,
+    /// Poll faster while threatening weather is around, see `AdaptivePolling`
+    pub adaptive_polling: Option<AdaptivePolling>,
+    /// Address to serve Prometheus metrics on, e.g. `127.0.0.1:9812`. Only read at startup
+    pub metrics_address: Option<String>,
+    /// Where the daemon writes its state for `nwsd health`, see `state::get_state_path`
+    pub state_file: Option<String>,
+    /// Where alerts go when the notification server can't be reached, see `FallbackSink`
+    pub notification_fallback: FallbackSink,
+}
+
+/// A named point to check for alerts
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Location {
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
 }
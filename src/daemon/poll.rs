@@ -0,0 +1,133 @@
+use std::sync::Arc;
+
+use tokio::task::spawn_blocking;
+
+use crate::{
+    daemon::{Config, Location},
+    print_debug, print_info,
+    weather::{
+        forecast::{fetch_forecast, fetch_point, ForecastPeriod},
+        outlook::{fetch_spc_risk, SpcRisk},
+        weather::{
+            extract_weather_features, fetch_weather_text, filter_features_for_location,
+            get_alerts_url, Feature,
+        },
+    },
+};
+
+/// What to fetch besides the active alerts
+#[derive(Clone, Copy)]
+pub struct PollRequest {
+    pub hourly_forecast: bool,
+    pub daily_forecast: bool,
+    pub spc_outlook: bool,
+}
+
+/// Everything fetched for a location in one poll
+pub struct LocationPoll {
+    pub location: Location,
+    pub alerts: Result<Vec<Feature>, String>,
+    pub hourly_forecast: Option<Result<Vec<ForecastPeriod>, String>>,
+    pub daily_forecast: Option<Result<Vec<ForecastPeriod>, String>>,
+}
+
+pub struct PollResults {
+    pub locations: Vec<LocationPoll>,
+    pub spc_risk: Option<Result<Option<SpcRisk>, String>>,
+}
+
+/// Fetches everything a poll needs for every location at the same time. The requests are
+/// blocking, so each location is fetched on tokio's blocking thread pool
+pub async fn fetch_poll(config: Arc<Config>, request: PollRequest, debug: bool) -> PollResults {
+    let location_handles: Vec<_> = config
+        .get_locations()
+        .into_iter()
+        .map(|location| {
+            let config = Arc::clone(&config);
+            let handle = spawn_blocking({
+                let location = location.clone();
+                move || poll_location(&config, location, request, debug)
+            });
+            (location, handle)
+        })
+        .collect();
+
+    let spc_handle = request.spc_outlook.then(|| {
+        let config = Arc::clone(&config);
+        spawn_blocking(move || fetch_spc_risk(&config, &config.get_locations()))
+    });
+
+    let mut locations = vec![];
+    for (location, handle) in location_handles {
+        locations.push(match handle.await {
+            Ok(poll) => poll,
+            Err(e) => LocationPoll {
+                location,
+                alerts: Err(format!("Polling stopped unexpectedly: {}", e)),
+                hourly_forecast: None,
+                daily_forecast: None,
+            },
+        });
+    }
+
+    let spc_risk = match spc_handle {
+        Some(handle) => Some(
+            handle
+                .await
+                .unwrap_or_else(|e| Err(format!("Fetching stopped unexpectedly: {}", e))),
+        ),
+        None => None,
+    };
+
+    PollResults {
+        locations,
+        spc_risk,
+    }
+}
+
+fn poll_location(
+    config: &Config,
+    location: Location,
+    request: PollRequest,
+    debug: bool,
+) -> LocationPoll {
+    print_info!(
+        "Updating weather service information for {} from {}",
+        location.name,
+        get_alerts_url(&location)
+    );
+
+    let alerts = fetch_weather_text(config, &location).and_then(|response_text| {
+        if debug {
+            print_debug!("Response text {:?}", response_text);
+        }
+        extract_weather_features(response_text)
+            .map(|features| filter_features_for_location(features, &location, config))
+    });
+
+    let (hourly_forecast, daily_forecast) = if request.hourly_forecast || request.daily_forecast {
+        match fetch_point(config, &location) {
+            Ok(point) => (
+                request
+                    .hourly_forecast
+                    .then(|| fetch_forecast(config, &point, true)),
+                request
+                    .daily_forecast
+                    .then(|| fetch_forecast(config, &point, false)),
+            ),
+            Err(e) => (
+                request.hourly_forecast.then(|| Err(e.clone())),
+                request.daily_forecast.then_some(Err(e)),
+            ),
+        }
+    } else {
+        (None, None)
+    };
+
+    LocationPoll {
+        location,
+        alerts,
+        hourly_forecast,
+        daily_forecast,
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+    daemon::{
+        config::{check_direcotry, load_config, SYSTEM_CONFIG_PATH},
+        run::DaemonEvent,
+        Config, Daemon,
+    },
+    print_done, print_error, print_info, print_warn,
+};
+
+/// How often the config files are checked for changes
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Notices when the config files are modified
+struct ConfigWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl ConfigWatcher {
+    fn new(config_directory: Option<String>) -> Self {
+        let files = [
+            PathBuf::from(SYSTEM_CONFIG_PATH),
+            check_direcotry(config_directory),
+        ]
+        .into_iter()
+        .map(|path| {
+            let modified = get_modified(&path);
+            (path, modified)
+        })
+        .collect();
+
+        ConfigWatcher { files }
+    }
+
+    /// Whether a config file changed since the last call
+    fn has_changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, modified) in &mut self.files {
+            let current = get_modified(path);
+            if current != *modified {
+                *modified = current;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// Sends a reload event whenever a config file is modified
+pub fn watch_config_files(config_directory: Option<String>, events: UnboundedSender<DaemonEvent>) {
+    let mut watcher = ConfigWatcher::new(config_directory);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            if watcher.has_changed() && events.send(DaemonEvent::Reload).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+fn get_modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Loads the config again and swaps it in if it is valid. Acknowledged alerts and the rest of the
+/// daemon state are kept, an invalid config is logged and the current one stays in use
+pub fn reload_config(daemon: &mut Daemon) {
+    print_info!("Config changed, reloading");
+
+    let loaded = match load_config(daemon.config_directory.clone()) {
+        Ok(loaded) => loaded,
+        Err(errors) => {
+            for error in errors {
+                print_error!(error);
+            }
+            print_error!("Keeping the current config until the errors are fixed");
+            return;
+        }
+    };
+
+    // Editors can briefly remove the file while saving, don't fall back to the defaults then
+    if loaded.files.is_empty() {
+        print_error!("No config file found, keeping the current config");
+        return;
+    }
+
+    for warning in &loaded.warnings {
+        print_warn!(warning);
+    }
+
+    let changed = get_changed_options(&daemon.config, &loaded.config);
+    daemon.config = Arc::new(loaded.config);
+    match changed.is_empty() {
+        true => print_done!("Reloaded config, nothing changed"),
+        false => print_done!("Reloaded config, changed {}", changed.join(", ")),
+    }
+}
+
+/// The top level options whose values differ between two configs
+fn get_changed_options(old: &Config, new: &Config) -> Vec<String> {
+    match (serde_json::to_value(old), serde_json::to_value(new)) {
+        (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) => new
+            .iter()
+            .filter(|(key, value)| old.get(key.as_str()) != Some(value))
+            .map(|(key, _)| key.clone())
+            .collect(),
+        _ => vec![],
+    }
+}
@@ -1,82 +1,566 @@
-use core::time;
-use std::thread;
+use chrono::{DateTime, Duration, Local};
+use sd_notify::NotifyState;
+use std::{
+    fs,
+    io::{self, Write},
+    sync::Arc,
+};
+use tokio::{
+    sync::mpsc,
+    task::spawn_blocking,
+    time::{sleep_until, Instant},
+};
 
 use crate::{
-    daemon::Daemon,
-    print_debug, print_error, print_info,
-    weather::weather::{extract_weather_features, send_notification, Event},
+    daemon::{
+        metrics::{serve_metrics, METRICS},
+        poll::{fetch_poll, LocationPoll, PollRequest, PollResults},
+        reload::{reload_config, watch_config_files},
+        schedule::{
+            assess_threat, get_next_interval, is_spc_check_due, record_spc_risk, ThreatLevel,
+        },
+        signals::listen_for_signals,
+        state::{get_state_path, remove_state, write_state},
+        systemd::{get_watchdog_timeout, notify_systemd},
+        Daemon, PendingOnset,
+    },
+    print_error, print_fatal, print_info, print_warn,
+    weather::fallback::send_alert,
+    weather::filters::should_notify,
+    weather::forecast::send_forecast_notification,
+    weather::geometry::Proximity,
+    weather::rules::evaluate_rule,
+    weather::vtec::{get_alert_change, AlertChange},
+    weather::weather::{get_proximity, AlertProperties, Event, Feature},
 };
 
-pub fn run(mut daemon: Daemon) {
+/// Things that happen outside of the poll cycle, sent to the event loop by the tasks listening
+/// for them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DaemonEvent {
+    /// Poll right away
+    Refresh,
+    /// A config file changed or SIGHUP was received
+    Reload,
+    Shutdown,
+}
+
+pub fn run(daemon: Daemon) {
     print_info!("Starting up daemon");
 
     print_info!("{:?}", daemon);
 
-    let honk_shooo = time::Duration::from_millis(1000 * daemon.config.update_interval);
+    // Fetches run on the blocking thread pool, one thread is enough for everything else
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            print_fatal!("Could not start the event loop: {}", e);
+            return;
+        }
+    };
+    runtime.block_on(event_loop(daemon));
+    // Don't wait for requests that were cancelled by shutting down
+    runtime.shutdown_background();
+}
+
+/// Owns the daemon state. Polls, waits for the next poll and handles events from the signal and
+/// config file tasks, one at a time
+async fn event_loop(mut daemon: Daemon) {
+    let (sender, mut events) = mpsc::unbounded_channel();
+    listen_for_signals(sender.clone());
+    watch_config_files(daemon.config_directory.clone(), sender);
+    if let Some(address) = &daemon.config.metrics_address {
+        serve_metrics(address).await;
+    }
+
+    if let Some(timeout) = get_watchdog_timeout() {
+        if timeout.as_secs() <= daemon.config.get_max_update_interval() {
+            print_warn!("The systemd watchdog timeout of {}s is shorter than the update interval, run nwsd install-service --force to update it", timeout.as_secs());
+        }
+    }
+    save_state(&mut daemon).await;
+    notify_systemd(&[NotifyState::Ready]);
+
     loop {
-        let url = format!(
-            "https://api.weather.gov/alerts/active?point={},{}",
-            daemon.config.lat, daemon.config.lon
-        );
-        print_info!("Updating weather service information from {}", url);
+        let now = Local::now();
+        let request = PollRequest {
+            hourly_forecast: !daemon.config.forecast_rules.is_empty(),
+            daily_forecast: is_morning_forecast_due(&daemon, now),
+            spc_outlook: is_spc_check_due(&daemon, now),
+        };
+        METRICS.polls.inc();
+        let poll = fetch_poll(Arc::clone(&daemon.config), request, daemon.debug);
+        tokio::pin!(poll);
+
+        let mut reload_requested = false;
+        let results = loop {
+            tokio::select! {
+                results = &mut poll => break results,
+                event = events.recv() => match event {
+                    Some(DaemonEvent::Reload) => reload_requested = true,
+                    // Already polling
+                    Some(DaemonEvent::Refresh) => {}
+                    Some(DaemonEvent::Shutdown) | None => return shut_down(&daemon),
+                },
+            }
+        };
+        handle_poll_results(&mut daemon, results).await;
+
+        if reload_requested {
+            reload(&mut daemon).await;
+            continue;
+        }
+
+        let next_poll = Instant::now() + std::time::Duration::from_secs(daemon.poll_interval);
+        loop {
+            // Wake up for reminders that are due before the next poll
+            let wake = get_next_onset(&daemon).map_or(next_poll, |onset| onset.min(next_poll));
+            tokio::select! {
+                _ = sleep_until(wake) => {
+                    if wake >= next_poll {
+                        break;
+                    }
+                    send_onset_reminders(&mut daemon).await;
+                }
+                event = events.recv() => match event {
+                    Some(DaemonEvent::Refresh) => {
+                        print_info!("Received SIGUSR1, checking for alerts now");
+                        break;
+                    }
+                    Some(DaemonEvent::Reload) => {
+                        reload(&mut daemon).await;
+                        break;
+                    }
+                    Some(DaemonEvent::Shutdown) | None => return shut_down(&daemon),
+                },
+            }
+        }
+    }
+}
+
+/// When the earliest onset reminder that hasn't come yet is due. Reminders that are already due
+/// couldn't be sent and wait for the next poll
+fn get_next_onset(daemon: &Daemon) -> Option<Instant> {
+    let now = Local::now();
+    daemon
+        .pending_onsets
+        .iter()
+        .filter(|pending| pending.onset > now)
+        .map(|pending| pending.onset)
+        .min()
+        .and_then(|onset| (onset.with_timezone(&Local) - now).to_std().ok())
+        .map(|until| Instant::now() + until)
+}
+
+/// Notifies everything new in a poll and updates the daemon state from it
+async fn handle_poll_results(daemon: &mut Daemon, results: PollResults) {
+    let now = Local::now();
+    if let Some(risk) = results.spc_risk {
+        record_spc_risk(daemon, risk, now);
+    }
 
-        // Send GET request to the API
-        let client_builder = reqwest::blocking::Client::builder();
+    forget_ended_alerts(daemon, now);
 
-        let client = client_builder
-            .user_agent(&daemon.config.user_agent)
-            .build()
-            .expect("Could not build request client"); // TODO: REPLACE WITH CONFIG OPTION
-        let response = match client.get(url).send() {
-            Ok(r) => r,
+    let mut failures: Vec<String> = vec![];
+    let mut active_events: Vec<Event> = vec![];
+
+    for poll in &results.locations {
+        let weather_features = match &poll.alerts {
+            Ok(features) => features,
             Err(e) => {
-                print_error!("Failed to fetch data. Response: {}", e.to_string());
+                print_error!("{}", e);
+                failures.push(format!("{}: {}", poll.location.name, e));
                 continue;
             }
         };
 
-        // Ensure the request was successful
-        if !response.status().is_success() {
-            print_error!(
-                "Failed to fetch data. Response: {}",
-                response.text().unwrap_or("None".to_string())
-            );
+        active_events.extend(
+            weather_features
+                .iter()
+                .map(|feature| feature.properties.event.clone()),
+        );
+        for feature in weather_features {
+            // The same alert shows up for every location it covers, and updates of an alert
+            // share its identities
+            let change = get_alert_change(&feature.properties, |identity| {
+                daemon.acknowledged_alerts.contains(identity)
+            });
+            match change {
+                AlertChange::Unchanged => {
+                    acknowledge(daemon, &feature.properties);
+                    continue;
+                }
+                AlertChange::Ended => {
+                    end_alert(daemon, &feature.properties);
+                    continue;
+                }
+                _ => {}
+            }
+
+            if !should_notify(&feature.properties, &daemon.config.alert_filters) {
+                continue;
+            }
+
+            // feature.properties.severity = Severity::Extreme; For testing
+
+            let proximity = get_proximity(feature, &poll.location);
+            match change.get_headline_prefix() {
+                Some(prefix) => {
+                    let mut feature = feature.clone();
+                    feature.properties.headline =
+                        format!("{}: {}", prefix, feature.properties.headline);
+                    deliver_alert(daemon, &feature, proximity.as_ref()).await;
+                }
+                None => deliver_alert(daemon, feature, proximity.as_ref()).await,
+            }
+        }
+    }
+
+    report_poll_result(results.locations.len(), &failures, active_events.len());
+    record_poll_metrics(daemon, &results.locations, failures.len(), now);
+
+    check_forecast_rules(daemon, &results.locations, now).await;
+
+    send_onset_reminders(daemon).await;
+
+    send_morning_forecast(daemon, &results.locations, now).await;
+
+    let threat = assess_threat(daemon, &active_events, !failures.is_empty());
+    schedule_next_poll(daemon, &threat);
+
+    let error = (failures.len() == results.locations.len())
+        .then(|| failures.first().cloned())
+        .flatten();
+    daemon.state.record_poll(now, error);
+    save_state(daemon).await;
+}
+
+/// Writes the daemon state for `nwsd health`. Asking the notification server about itself is a
+/// blocking D-Bus call, so it's made on the blocking thread pool
+async fn save_state(daemon: &mut Daemon) {
+    daemon.state.poll_interval = daemon.poll_interval;
+    let mut state = daemon.state.clone();
+    match spawn_blocking(move || {
+        state.check_notification_server();
+        state
+    })
+    .await
+    {
+        Ok(state) => daemon.state = state,
+        Err(e) => print_error!("Could not check the notification server: {}", e),
+    }
+    if let Err(e) = write_state(&daemon.config, &daemon.state) {
+        print_error!("{}", e);
+    }
+}
+
+/// Updates the metrics that describe the daemon's state after a poll, alerts covering several
+/// locations are counted once
+fn record_poll_metrics(
+    daemon: &Daemon,
+    locations: &[LocationPoll],
+    failures: usize,
+    now: DateTime<Local>,
+) {
+    if failures < locations.len() {
+        METRICS.last_successful_poll.set(now.timestamp() as f64);
+    }
+
+    METRICS
+        .acknowledged_alerts
+        .set(daemon.acknowledged_alerts.len() as i64);
+    let forgotten = daemon.acknowledged_alerts.get_evicted_count();
+    METRICS
+        .forgotten_alerts
+        .inc_by(forgotten.saturating_sub(METRICS.forgotten_alerts.get()));
+
+    let mut seen: Vec<&str> = vec![];
+    METRICS.active_alerts.reset();
+    for feature in locations
+        .iter()
+        .filter_map(|poll| poll.alerts.as_ref().ok())
+        .flatten()
+    {
+        if seen.contains(&feature.properties.id.as_str()) {
             continue;
         }
+        seen.push(&feature.properties.id);
+        METRICS
+            .active_alerts
+            .with_label_values(&[
+                &format!("{:?}", feature.properties.severity),
+                &feature.properties.event.to_string(),
+            ])
+            .inc();
+    }
+}
 
-        let response_text = response.text().expect("Failed to unwrap response text");
+/// Keeps the acknowledged alerts from growing for as long as the daemon runs
+fn forget_ended_alerts(daemon: &mut Daemon, now: DateTime<Local>) {
+    let evicted = daemon.acknowledged_alerts.evict_expired(now);
+    if evicted > 0 || daemon.debug {
+        print_info!(
+            "Forgot {} ended alerts, remembering {} ({} forgotten since starting)",
+            evicted,
+            daemon.acknowledged_alerts.len(),
+            daemon.acknowledged_alerts.get_evicted_count()
+        );
+    }
+}
 
-        if daemon.debug {
-            print_debug!("Response text {:?}", response_text);
+async fn reload(daemon: &mut Daemon) {
+    notify_systemd(&[NotifyState::Reloading]);
+    let state_path = get_state_path(&daemon.config);
+    reload_config(daemon);
+    if get_state_path(&daemon.config) != state_path {
+        if let Ok(path) = state_path {
+            let _ = fs::remove_file(path);
         }
+        save_state(daemon).await;
+    }
+    notify_systemd(&[NotifyState::Ready]);
+}
 
-        let weather_features = extract_weather_features(response_text);
+/// Picks the interval until the next poll, logging when it changes
+fn schedule_next_poll(daemon: &mut Daemon, threat: &ThreatLevel) {
+    let interval = get_next_interval(&daemon.config, daemon.poll_interval, threat);
+    if interval != daemon.poll_interval {
+        match threat {
+            ThreatLevel::Elevated(reason) => {
+                print_info!("Checking every {}s while {}", interval, reason)
+            }
+            _ => print_info!("Checking every {}s", interval),
+        }
+    }
+    daemon.poll_interval = interval;
+}
 
-        let mut already_alerted: Vec<Event> = vec![];
+/// Reports the result of a poll to systemd, pinging the watchdog when the API could be reached for
+/// at least one location
+fn report_poll_result(locations: usize, failures: &[String], active_alerts: usize) {
+    let checked = Local::now().format("%-I:%M %p");
+    let status = match failures.first() {
+        None => format!("Checked at {}, {} active alerts", checked, active_alerts),
+        Some(failure) => format!(
+            "Check at {} failed for {} of {} locations, {}",
+            checked,
+            failures.len(),
+            locations,
+            failure
+        ),
+    };
+    notify_systemd(&[NotifyState::Status(&status)]);
 
-        for feature in weather_features {
-            if daemon.acknowledged_alerts.contains(&feature.properties.id) {
-                continue;
+    if failures.len() < locations {
+        notify_systemd(&[NotifyState::Watchdog]);
+    }
+}
+
+fn shut_down(daemon: &Daemon) {
+    notify_systemd(&[NotifyState::Stopping]);
+    print_info!("Shutting down");
+    remove_state(&daemon.config);
+    if !daemon.pending_onsets.is_empty() {
+        print_warn!(
+            "{} onset reminders will not be sent",
+            daemon.pending_onsets.len()
+        );
+    }
+    // Logs are usually redirected to a file or the journal, don't lose the last lines
+    let _ = io::stdout().flush();
+}
+
+/// Whether an alert, or an earlier message about the same hazard, was already notified
+fn is_acknowledged(daemon: &Daemon, alert_properties: &AlertProperties) -> bool {
+    alert_properties
+        .get_identities()
+        .iter()
+        .any(|identity| daemon.acknowledged_alerts.contains(identity))
+}
+
+fn acknowledge(daemon: &mut Daemon, alert_properties: &AlertProperties) {
+    daemon
+        .acknowledged_alerts
+        .acknowledge(alert_properties, Local::now());
+}
+
+/// Forgets the onset reminders of a cancelled or expired event
+fn end_alert(daemon: &mut Daemon, alert_properties: &AlertProperties) {
+    acknowledge(daemon, alert_properties);
+
+    let identities = alert_properties.get_identities();
+    daemon.pending_onsets.retain(|pending| {
+        !pending
+            .feature
+            .properties
+            .get_identities()
+            .iter()
+            .any(|identity| identities.contains(identity))
+    });
+    print_info!("{} has ended", alert_properties.event);
+}
+
+/// Notifies an alert and acknowledges it once delivered, alerts that couldn't be delivered are
+/// tried again on the next poll
+async fn deliver_alert(daemon: &mut Daemon, feature: &Feature, proximity: Option<&Proximity>) {
+    match proximity {
+        Some(proximity) => println!("{} ({})", feature, proximity),
+        None => println!("{}", feature),
+    }
+
+    if let Err(e) = send_alert_in_background(daemon, &feature.properties, proximity).await {
+        print_error!("{}, trying again on the next check", e);
+        return;
+    }
+    acknowledge(daemon, &feature.properties);
+
+    if daemon.config.onset_reminder_minutes > 0 {
+        let now = Local::now();
+        if let Some(onset) = feature.properties.get_future_onset(now) {
+            if onset.signed_duration_since(now)
+                > Duration::minutes(daemon.config.onset_reminder_minutes)
+            {
+                daemon.pending_onsets.push(PendingOnset {
+                    onset,
+                    feature: feature.clone(),
+                    proximity: proximity.cloned(),
+                });
             }
+        }
+    }
+}
+
+/// Sends an alert on the blocking thread pool, a notification that can't be shown yet is retried
+/// with a delay which would otherwise hold up the event loop
+async fn send_alert_in_background(
+    daemon: &Daemon,
+    alert_properties: &AlertProperties,
+    proximity: Option<&Proximity>,
+) -> Result<(), String> {
+    let config = Arc::clone(&daemon.config);
+    let alert_properties = alert_properties.clone();
+    let proximity = proximity.cloned();
+    spawn_blocking(move || send_alert(&alert_properties, proximity.as_ref(), &config).map(|_| ()))
+        .await
+        .map_err(|e| format!("Could not send alert: {}", e))?
+}
+
+/// Notifies alerts issued well in advance again once their onset has arrived
+async fn send_onset_reminders(daemon: &mut Daemon) {
+    let now = Local::now();
+    let (due, pending): (Vec<PendingOnset>, Vec<PendingOnset>) = daemon
+        .pending_onsets
+        .drain(..)
+        .partition(|pending| pending.onset <= now);
+    daemon.pending_onsets = pending;
+
+    for reminder in due {
+        if reminder.feature.properties.is_expired(now) {
+            continue;
+        }
+
+        let mut properties = reminder.feature.properties.clone();
+        properties.headline = format!("Now in effect: {}", properties.headline);
+
+        match &reminder.proximity {
+            Some(proximity) => println!("{} ({})", reminder.feature, proximity),
+            None => println!("{}", reminder.feature),
+        }
+
+        if let Err(e) =
+            send_alert_in_background(daemon, &properties, reminder.proximity.as_ref()).await
+        {
+            print_error!("{}, trying again on the next check", e);
+            daemon.pending_onsets.push(reminder);
+        }
+    }
+}
 
-            // Sometimes current weather alerts will contain duplicate entries. Just choose the
-            // first one
-            if already_alerted.contains(&feature.properties.event) {
+/// Evaluates the configured forecast rules against each location's hourly forecast, sending any
+/// matches like a regular alert
+async fn check_forecast_rules(
+    daemon: &mut Daemon,
+    locations: &[LocationPoll],
+    now: DateTime<Local>,
+) {
+    for poll in locations {
+        let periods = match &poll.hourly_forecast {
+            Some(Ok(periods)) => periods,
+            Some(Err(e)) => {
+                print_error!(
+                    "Could not fetch hourly forecast for {}: {}",
+                    poll.location.name,
+                    e
+                );
                 continue;
             }
+            None => continue,
+        };
 
-            already_alerted.push(feature.properties.event.clone());
+        let triggered: Vec<AlertProperties> = daemon
+            .config
+            .forecast_rules
+            .iter()
+            .filter_map(|rule| evaluate_rule(rule, periods, &poll.location, now))
+            .collect();
 
-            daemon
-                .acknowledged_alerts
-                .push(feature.properties.id.clone());
+        for properties in triggered {
+            if is_acknowledged(daemon, &properties) {
+                continue;
+            }
+            deliver_alert(
+                daemon,
+                &Feature {
+                    properties,
+                    geometry: None,
+                },
+                None,
+            )
+            .await;
+        }
+    }
+}
 
-            println!("{}", feature);
+/// Whether the morning forecast should be fetched, at the first poll after the configured time
+/// each day
+fn is_morning_forecast_due(daemon: &Daemon, now: DateTime<Local>) -> bool {
+    daemon.config.morning_forecast.is_some_and(|forecast_time| {
+        now.time() >= forecast_time && daemon.last_morning_forecast != Some(now.date_naive())
+    })
+}
 
-            // feature.properties.severity = Severity::Extreme; For testing
+/// Sends the forecast for every location once all of them could be fetched, on the blocking thread
+/// pool like alerts
+async fn send_morning_forecast(
+    daemon: &mut Daemon,
+    locations: &[LocationPoll],
+    now: DateTime<Local>,
+) {
+    let mut forecasts = vec![];
+    for poll in locations {
+        match &poll.daily_forecast {
+            Some(Ok(periods)) => forecasts.push((periods.clone(), poll.location.clone())),
+            Some(Err(e)) => {
+                // Try again on the next poll
+                print_error!("Could not fetch forecast for {}: {}", poll.location.name, e);
+                return;
+            }
+            None => return,
+        }
+    }
 
-            send_notification(&feature.properties, &daemon.config);
+    let config = Arc::clone(&daemon.config);
+    let sent = spawn_blocking(move || {
+        for (periods, location) in forecasts {
+            send_forecast_notification(&periods, &location, &config);
         }
-        thread::sleep(honk_shooo);
+    })
+    .await;
+    if let Err(e) = sent {
+        print_error!("Could not send forecast notification: {}", e);
     }
+    daemon.last_morning_forecast = Some(now.date_naive());
 }
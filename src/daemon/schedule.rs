@@ -0,0 +1,189 @@
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    daemon::{Config, Daemon},
+    print_error,
+    weather::{outlook::SpcRisk, weather::Event},
+};
+
+/// How much longer each calm poll waits than the one before, until `max_interval`
+const BACKOFF_FACTOR: f64 = 1.5;
+
+/// The SPC outlook is only issued a few times a day, don't fetch it every poll
+const SPC_CHECK_MINUTES: i64 = 30;
+
+/// Polls faster while threatening weather is around and backs off while it is calm
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AdaptivePolling {
+    /// Seconds between polls while one of `active_events` is in effect or the SPC risk is
+    /// elevated
+    pub min_interval: u64,
+    /// The longest the interval backs off to while no alerts are active
+    pub max_interval: u64,
+    pub active_events: Vec<Event>,
+    /// Also poll at `min_interval` while a location is in a Storm Prediction Center day 1 risk at
+    /// or above this
+    pub spc_risk: Option<SpcRisk>,
+}
+
+impl Default for AdaptivePolling {
+    fn default() -> Self {
+        AdaptivePolling {
+            min_interval: 60,
+            max_interval: 900,
+            active_events: vec![
+                Event::TornadoWatch,
+                Event::TornadoWarning,
+                Event::SevereThunderstormWatch,
+                Event::SevereThunderstormWarning,
+                Event::FlashFloodWarning,
+            ],
+            spc_risk: None,
+        }
+    }
+}
+
+/// What the last poll found, from most to least threatening
+pub enum ThreatLevel {
+    /// Poll as often as allowed, with the reason why
+    Elevated(String),
+    /// Alerts are active, or the API couldn't be reached
+    Active,
+    Calm,
+}
+
+/// Judges how threatening the weather is from the events active at every location
+pub fn assess_threat(daemon: &Daemon, active_events: &[Event], fetch_failed: bool) -> ThreatLevel {
+    if let Some(adaptive) = &daemon.config.adaptive_polling {
+        if let Some(event) = active_events
+            .iter()
+            .find(|event| adaptive.active_events.contains(event))
+        {
+            return ThreatLevel::Elevated(format!("{} is active", event));
+        }
+
+        if let (Some(threshold), Some(risk)) = (adaptive.spc_risk, daemon.spc_risk) {
+            if risk >= threshold {
+                return ThreatLevel::Elevated(format!("{} from the Storm Prediction Center", risk));
+            }
+        }
+    }
+
+    match active_events.is_empty() && !fetch_failed {
+        true => ThreatLevel::Calm,
+        false => ThreatLevel::Active,
+    }
+}
+
+/// Seconds to wait before the next poll, `update_interval` unless adaptive polling is configured
+pub fn get_next_interval(config: &Config, previous: u64, threat: &ThreatLevel) -> u64 {
+    let adaptive = match &config.adaptive_polling {
+        Some(adaptive) => adaptive,
+        None => return config.update_interval,
+    };
+
+    let normal = config
+        .update_interval
+        .max(adaptive.min_interval)
+        .min(adaptive.max_interval);
+    match threat {
+        ThreatLevel::Elevated(_) => adaptive.min_interval,
+        ThreatLevel::Active => normal,
+        ThreatLevel::Calm => {
+            ((previous.max(normal) as f64 * BACKOFF_FACTOR) as u64).min(adaptive.max_interval)
+        }
+    }
+}
+
+/// Whether the SPC risk is wanted and hasn't been checked recently
+pub fn is_spc_check_due(daemon: &Daemon, now: DateTime<Local>) -> bool {
+    daemon
+        .config
+        .adaptive_polling
+        .as_ref()
+        .is_some_and(|adaptive| adaptive.spc_risk.is_some())
+        && daemon
+            .last_spc_check
+            .is_none_or(|checked| now - checked >= Duration::minutes(SPC_CHECK_MINUTES))
+}
+
+/// Keeps the SPC risk for the configured locations. The last known risk is kept until the next
+/// check when the outlook couldn't be fetched
+pub fn record_spc_risk(
+    daemon: &mut Daemon,
+    risk: Result<Option<SpcRisk>, String>,
+    now: DateTime<Local>,
+) {
+    daemon.last_spc_check = Some(now);
+    match risk {
+        Ok(risk) => daemon.spc_risk = risk,
+        Err(e) => print_error!("Could not fetch the SPC outlook: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adaptive_config() -> Config {
+        Config {
+            update_interval: 300,
+            adaptive_polling: Some(AdaptivePolling::default()),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn fixed_interval_without_adaptive_polling() {
+        let config = Config {
+            update_interval: 120,
+            ..Config::default()
+        };
+
+        assert_eq!(get_next_interval(&config, 60, &ThreatLevel::Calm), 120);
+        assert_eq!(
+            get_next_interval(&config, 60, &ThreatLevel::Elevated("test".to_string())),
+            120
+        );
+    }
+
+    #[test]
+    fn calm_polls_back_off_to_the_max_interval() {
+        let config = adaptive_config();
+
+        let mut interval = 300;
+        let mut intervals = vec![];
+        for _ in 0..5 {
+            interval = get_next_interval(&config, interval, &ThreatLevel::Calm);
+            intervals.push(interval);
+        }
+
+        assert_eq!(intervals, [450, 675, 900, 900, 900]);
+    }
+
+    #[test]
+    fn threats_reset_the_back_off() {
+        let config = adaptive_config();
+
+        assert_eq!(get_next_interval(&config, 900, &ThreatLevel::Active), 300);
+        assert_eq!(
+            get_next_interval(&config, 900, &ThreatLevel::Elevated("test".to_string())),
+            60
+        );
+        // Backing off starts from the normal interval, not the elevated one
+        assert_eq!(get_next_interval(&config, 60, &ThreatLevel::Calm), 450);
+    }
+
+    #[test]
+    fn update_interval_is_kept_within_the_bounds() {
+        let mut config = adaptive_config();
+        config.update_interval = 10;
+        assert_eq!(get_next_interval(&config, 10, &ThreatLevel::Active), 60);
+
+        config.update_interval = 3600;
+        assert_eq!(get_next_interval(&config, 10, &ThreatLevel::Active), 900);
+        assert_eq!(get_next_interval(&config, 3600, &ThreatLevel::Calm), 900);
+    }
+}
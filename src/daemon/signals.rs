@@ -0,0 +1,42 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::mpsc::UnboundedSender,
+};
+
+use crate::{daemon::run::DaemonEvent, print_error, print_warn};
+
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Listens for SIGTERM/SIGINT to shut down, SIGUSR1 to poll right away and SIGHUP to reload the
+/// config. A second SIGTERM or SIGINT exits immediately, in case shutting down is stuck
+pub fn listen_for_signals(events: UnboundedSender<DaemonEvent>) {
+    for (kind, name, event) in [
+        (SignalKind::terminate(), "SIGTERM", DaemonEvent::Shutdown),
+        (SignalKind::interrupt(), "SIGINT", DaemonEvent::Shutdown),
+        (SignalKind::user_defined1(), "SIGUSR1", DaemonEvent::Refresh),
+        (SignalKind::hangup(), "SIGHUP", DaemonEvent::Reload),
+    ] {
+        let mut stream = match signal(kind) {
+            Ok(stream) => stream,
+            Err(e) => {
+                print_error!("Could not listen for {}: {}", name, e);
+                continue;
+            }
+        };
+
+        let events = events.clone();
+        tokio::spawn(async move {
+            while stream.recv().await.is_some() {
+                if event == DaemonEvent::Shutdown && SHUTTING_DOWN.swap(true, Ordering::Relaxed) {
+                    print_warn!("Received {} again, exiting immediately", name);
+                    std::process::exit(1);
+                }
+                if events.send(event).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+}
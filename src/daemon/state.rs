@@ -0,0 +1,120 @@
+use std::{fs, path::PathBuf};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::daemon::Config;
+
+/// What the running daemon last reported about itself, written after every poll for
+/// `nwsd health`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DaemonState {
+    pub pid: u32,
+    pub started: DateTime<Local>,
+    pub last_poll: Option<DateTime<Local>>,
+    /// The last poll that reached the API for at least one location
+    pub last_successful_poll: Option<DateTime<Local>>,
+    /// Polls in a row that couldn't reach the API for any location
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    /// Seconds until the next poll
+    pub poll_interval: u64,
+    /// The notification server's name and version, `None` when it couldn't be reached
+    pub notification_server: Option<String>,
+    pub notification_error: Option<String>,
+}
+
+impl DaemonState {
+    pub fn new(poll_interval: u64) -> Self {
+        DaemonState {
+            pid: std::process::id(),
+            started: Local::now(),
+            last_poll: None,
+            last_successful_poll: None,
+            consecutive_failures: 0,
+            last_error: None,
+            poll_interval,
+            notification_server: None,
+            notification_error: None,
+        }
+    }
+
+    /// Records the outcome of a poll, `error` is the first failure if every location failed
+    pub fn record_poll(&mut self, now: DateTime<Local>, error: Option<String>) {
+        self.last_poll = Some(now);
+        match error {
+            None => {
+                self.last_successful_poll = Some(now);
+                self.consecutive_failures = 0;
+            }
+            Some(error) => {
+                self.consecutive_failures += 1;
+                self.last_error = Some(error);
+            }
+        }
+    }
+
+    /// Asks the notification server who it is, which fails when it isn't running
+    pub fn check_notification_server(&mut self) {
+        match notify_rust::get_server_information() {
+            Ok(server) => {
+                self.notification_server = Some(format!("{} {}", server.name, server.version));
+                self.notification_error = None;
+            }
+            Err(e) => {
+                self.notification_server = None;
+                self.notification_error = Some(e.to_string());
+            }
+        }
+    }
+}
+
+/// Where the daemon writes its state, `state_file` or `nwsd/state.json` in the user's state
+/// directory
+pub fn get_state_path(config: &Config) -> Result<PathBuf, String> {
+    if let Some(path) = &config.state_file {
+        return Ok(PathBuf::from(path));
+    }
+    let mut path = dirs::state_dir()
+        .or_else(dirs::cache_dir)
+        .ok_or("Could not find a directory to keep the daemon state in, set state_file")?;
+    path.push("nwsd/state.json");
+    Ok(path)
+}
+
+/// Replaces the state file, through a temporary file so a reader never sees half of it
+pub fn write_state(config: &Config, state: &DaemonState) -> Result<(), String> {
+    let path = get_state_path(config)?;
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("Could not create {}: {}", directory.to_string_lossy(), e))?;
+    }
+
+    let json = serde_json::to_string_pretty(state)
+        .map_err(|e| format!("Could not serialize the daemon state: {}", e))?;
+    let mut temporary = path.clone().into_os_string();
+    temporary.push(".tmp");
+    fs::write(&temporary, json)
+        .and_then(|_| fs::rename(&temporary, &path))
+        .map_err(|e| format!("Could not write {}: {}", path.to_string_lossy(), e))
+}
+
+pub fn read_state(config: &Config) -> Result<DaemonState, String> {
+    let path = get_state_path(config)?;
+    let json = fs::read_to_string(&path).map_err(|e| {
+        format!(
+            "Could not read {}, is the daemon running? {}",
+            path.to_string_lossy(),
+            e
+        )
+    })?;
+    serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.to_string_lossy(), e))
+}
+
+/// Removes the state file when the daemon stops, so it isn't mistaken for a daemon that stopped
+/// polling
+pub fn remove_state(config: &Config) {
+    if let Ok(path) = get_state_path(config) {
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+use sd_notify::NotifyState;
+
+use crate::print_error;
+
+/// Tells systemd about the daemon's state when it runs as a `Type=notify` service, does nothing
+/// otherwise
+pub fn notify_systemd(states: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, states) {
+        print_error!("Could not notify systemd: {}", e);
+    }
+}
+
+/// How long systemd waits for a watchdog ping before restarting the service, if it does
+pub fn get_watchdog_timeout() -> Option<Duration> {
+    let mut usec = 0;
+    sd_notify::watchdog_enabled(false, &mut usec).then(|| Duration::from_micros(usec))
+}
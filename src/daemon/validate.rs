@@ -0,0 +1,232 @@
+use std::net::SocketAddr;
+
+use crate::{
+    daemon::{config::CONFIG_VERSION, Config, Location},
+    weather::speech::SpeechEngine,
+};
+
+/// Rough bounding boxes (min lat, max lat, min lon, max lon) of the areas api.weather.gov covers
+const NWS_COVERAGE: &[[f64; 4]] = &[
+    // Contiguous United States
+    [24.0, 50.0, -125.5, -66.0],
+    // Alaska
+    [51.0, 72.0, -180.0, -129.0],
+    // Western Aleutians, across the antimeridian
+    [51.0, 56.0, 172.0, 180.0],
+    // Hawaii
+    [18.5, 23.0, -161.0, -154.5],
+    // Puerto Rico and the Virgin Islands
+    [17.5, 18.8, -68.0, -64.5],
+    // Guam and the Northern Mariana Islands
+    [13.0, 21.0, 144.0, 146.5],
+    // American Samoa
+    [-15.0, -10.5, -171.5, -168.0],
+];
+
+/// Problems found in a config. Errors make the config unusable, warnings are just printed
+#[derive(Debug, Default)]
+pub struct ConfigIssues {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Checks the values of a parsed config
+pub fn validate_config(config: &Config) -> ConfigIssues {
+    let mut issues = ConfigIssues::default();
+
+    if config.config_version > CONFIG_VERSION {
+        issues.warnings.push(format!(
+            "The config is version {} but this version of nwsd only understands up to version {}, some options may be ignored",
+            config.config_version, CONFIG_VERSION
+        ));
+    }
+
+    if config.update_interval == 0 {
+        issues
+            .errors
+            .push("update_interval has to be at least 1 second".to_string());
+    }
+
+    if config.user_agent.trim().is_empty() {
+        issues.errors.push(
+            "user_agent can't be empty, the NWS API rejects requests without one".to_string(),
+        );
+    }
+
+    if config.locations.is_empty() {
+        issues
+            .errors
+            .push("At least one [[locations]] entry is needed to check for alerts".to_string());
+    }
+    for location in config.get_locations() {
+        validate_location(&location, &mut issues);
+    }
+
+    if config.nearby_radius_miles < 0.0 || !config.nearby_radius_miles.is_finite() {
+        issues
+            .errors
+            .push("nearby_radius_miles can't be negative".to_string());
+    }
+
+    for rule in &config.forecast_rules {
+        if rule.hours <= 0 {
+            issues.errors.push(format!(
+                "Forecast rule {:?} has to look at least 1 hour ahead",
+                rule.name
+            ));
+        }
+    }
+
+    if let Some(speech) = &config.speech {
+        if matches!(speech.engine, SpeechEngine::Piper) && speech.piper_model.is_none() {
+            issues
+                .errors
+                .push("speech.piper_model has to be set to use piper".to_string());
+        }
+    }
+
+    if let Some(adaptive) = &config.adaptive_polling {
+        if adaptive.min_interval == 0 {
+            issues
+                .errors
+                .push("adaptive_polling.min_interval has to be at least 1 second".to_string());
+        }
+        if adaptive.min_interval > adaptive.max_interval {
+            issues.errors.push(format!(
+                "adaptive_polling.min_interval ({}) can't be longer than max_interval ({})",
+                adaptive.min_interval, adaptive.max_interval
+            ));
+        }
+    }
+
+    if let Some(address) = &config.metrics_address {
+        if address.parse::<SocketAddr>().is_err() {
+            issues.errors.push(format!(
+                "metrics_address {:?} is not an address, expected an IP and port such as \"127.0.0.1:9812\"",
+                address
+            ));
+        }
+    }
+
+    issues
+}
+
+fn validate_location(location: &Location, issues: &mut ConfigIssues) {
+    let mut valid = true;
+    if !(-90.0..=90.0).contains(&location.lat) {
+        issues.errors.push(format!(
+            "Latitude {} of {} has to be between -90 and 90",
+            location.lat, location.name
+        ));
+        valid = false;
+    }
+    if !(-180.0..=180.0).contains(&location.lon) {
+        issues.errors.push(format!(
+            "Longitude {} of {} has to be between -180 and 180",
+            location.lon, location.name
+        ));
+        valid = false;
+    }
+
+    if valid && !is_covered_by_nws(location) {
+        let hint = match is_covered_by_nws(&Location {
+            name: location.name.clone(),
+            lat: location.lat,
+            lon: -location.lon,
+        }) {
+            true => " Western hemisphere longitudes are negative, is the sign of lon flipped?",
+            false => "",
+        };
+        issues.warnings.push(format!(
+            "{} ({}, {}) looks to be outside of the area the National Weather Service covers, no alerts will be found.{}",
+            location.name, location.lat, location.lon, hint
+        ));
+    }
+}
+
+pub fn is_covered_by_nws(location: &Location) -> bool {
+    NWS_COVERAGE
+        .iter()
+        .any(|[min_lat, max_lat, min_lon, max_lon]| {
+            (*min_lat..=*max_lat).contains(&location.lat)
+                && (*min_lon..=*max_lon).contains(&location.lon)
+        })
+}
+
+/// Every top level option `Config` has
+pub fn get_config_keys() -> Vec<String> {
+    // Serialize through JSON so fields that are None still show up as keys
+    match serde_json::to_value(Config::default()) {
+        Ok(serde_json::Value::Object(known)) => known.keys().cloned().collect(),
+        _ => vec![],
+    }
+}
+
+/// Keys in the config file that `Config` doesn't have, these are most likely typos. Keys in
+/// tables and arrays of tables are named by their path, e.g. `speech.engin` or
+/// `locations.0.nmae`
+pub fn find_unknown_keys(table: &toml::Table) -> Vec<String> {
+    let mut unknown = vec![];
+    // Errors are reported when the config itself is parsed
+    let _: Result<Config, _> =
+        serde_ignored::deserialize(toml::Value::Table(table.clone()), |path| {
+            unknown.push(format_key_path(&path))
+        });
+    unknown
+}
+
+/// Joins the keys and array indexes leading to an option with dots
+fn format_key_path(path: &serde_ignored::Path) -> String {
+    let (parent, segment) = match path {
+        serde_ignored::Path::Root => return String::new(),
+        serde_ignored::Path::Map { parent, key } => (parent, key.clone()),
+        serde_ignored::Path::Seq { parent, index } => (parent, index.to_string()),
+        serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => return format_key_path(parent),
+    };
+    match format_key_path(parent) {
+        parent if parent.is_empty() => segment,
+        parent => format!("{}.{}", parent, segment),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_unknown_keys_in_nested_tables() {
+        let table: toml::Table = toml::from_str(
+            r#"
+update_intervl = 60
+
+[speech]
+engine = "espeak-ng"
+min_severty = "Severe"
+
+[[locations]]
+name = "Home"
+lat = 35.0
+lon = -97.0
+elevation = 300
+
+[[alert_filters]]
+evnt = "Tornado Warning"
+"#,
+        )
+        .unwrap();
+
+        let mut unknown = find_unknown_keys(&table);
+        unknown.sort();
+        assert_eq!(
+            unknown,
+            [
+                "alert_filters.0.evnt",
+                "locations.0.elevation",
+                "speech.min_severty",
+                "update_intervl"
+            ]
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    env,
+    io::ErrorKind,
+    process::{Child, Command},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use notify_rust::{Hint, Notification, NotificationHandle, Timeout, Urgency};
+
+use crate::{
+    daemon::Config,
+    print_done, print_error,
+    weather::{
+        geometry::Proximity,
+        sound::{get_repeats_for_alert, get_sound_for_alert, play_sound, Sound},
+        weather::AlertProperties,
+    },
+};
+
+/// Shows an Extreme alert the way a Wireless Emergency Alert would: an always on top dialog (or a
+/// resident critical notification when no dialog could be shown) that stays up until it is
+/// acknowledged. Errors when neither could be shown, otherwise the returned handle waits for the
+/// alert to be acknowledged in the background so the daemon keeps polling while it is up
+pub fn show_emergency_alert(
+    alert_properties: &AlertProperties,
+    proximity: Option<&Proximity>,
+    config: &Config,
+) -> Result<JoinHandle<()>, String> {
+    let title = format!("Emergency Alert: {}", alert_properties.event);
+
+    let mut text = alert_properties.headline.clone();
+    if let Some(proximity) = proximity {
+        text = format!("{} {}\n\n{}", alert_properties.event, proximity, text);
+    }
+    if let Some(times) = alert_properties.get_time_summary() {
+        text = format!("{}\n{}", text, times);
+    }
+    if let Some(instruction) = &alert_properties.instruction {
+        text = format!("{}\n\n{}", text, instruction);
+    }
+
+    let presentation = match show_dialog(&title, &text) {
+        Some(dialog) => Presentation::Dialog(dialog),
+        None => Presentation::Notification(Box::new(show_resident_notification(
+            &title,
+            &text,
+            config.notification_icon_path.as_deref(),
+        )?)),
+    };
+
+    // A sound configured for the alert wins over the attention tone
+    let sound = get_sound_for_alert(alert_properties, config)
+        .cloned()
+        .or(config.extreme_alert_tone.then_some(Sound::Attention));
+    if let Some(sound) = sound {
+        play_sound(&sound, get_repeats_for_alert(alert_properties, config));
+    }
+
+    Ok(thread::spawn(move || {
+        match presentation {
+            // Any way of closing the dialog counts as acknowledging it
+            Presentation::Dialog(mut dialog) => {
+                let _ = dialog.wait();
+            }
+            // Returns on the acknowledge action or when the notification is dismissed
+            Presentation::Notification(handle) => handle.wait_for_action(|_| {}),
+        }
+        print_done!("{} acknowledged", title);
+    }))
+}
+
+/// What the emergency alert is shown with, waited on until it is acknowledged
+enum Presentation {
+    Dialog(Child),
+    Notification(Box<NotificationHandle>),
+}
+
+/// How long a dialog has to stay open to count as shown, a dialog that can't open its window
+/// exits with an error right away
+const DIALOG_STARTUP: Duration = Duration::from_millis(500);
+
+/// Starts a blocking dialog with the first dialog program that is installed and can open a
+/// window, returns `None` if none of them could
+fn show_dialog(title: &str, text: &str) -> Option<Child> {
+    // A systemd user service doesn't always get the session's display
+    if env::var_os("DISPLAY").is_none() && env::var_os("WAYLAND_DISPLAY").is_none() {
+        return None;
+    }
+
+    let escaped = escape_markup(text);
+    let dialogs: [(&str, Vec<&str>); 3] = [
+        (
+            "yad",
+            vec![
+                "--title",
+                title,
+                "--text",
+                &escaped,
+                "--image",
+                "dialog-warning",
+                "--button",
+                "Acknowledge:0",
+                "--on-top",
+                "--center",
+                "--sticky",
+                "--width",
+                "700",
+            ],
+        ),
+        (
+            "zenity",
+            vec![
+                "--warning",
+                "--title",
+                title,
+                "--text",
+                text,
+                "--no-markup",
+                "--ok-label",
+                "Acknowledge",
+                "--width",
+                "700",
+            ],
+        ),
+        ("kdialog", vec!["--title", title, "--sorry", text]),
+    ];
+
+    for (program, args) in dialogs {
+        let mut dialog = match Command::new(program).args(args).spawn() {
+            Ok(dialog) => dialog,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => {
+                print_error!("Could not show alert with {}: {}", program, e);
+                continue;
+            }
+        };
+        match check_dialog_started(&mut dialog) {
+            Ok(()) => return Some(dialog),
+            Err(e) => print_error!("Could not show alert with {}: {}", program, e),
+        }
+    }
+    None
+}
+
+/// Errors when the dialog exits unsuccessfully within `DIALOG_STARTUP`
+fn check_dialog_started(dialog: &mut Child) -> Result<(), String> {
+    let started = Instant::now();
+    while started.elapsed() < DIALOG_STARTUP {
+        match dialog.try_wait() {
+            Ok(None) => thread::sleep(Duration::from_millis(50)),
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(format!("it exited with {}", status)),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(())
+}
+
+/// Shows a critical notification that never times out with an acknowledge action
+fn show_resident_notification(
+    title: &str,
+    text: &str,
+    icon_path: Option<&str>,
+) -> Result<NotificationHandle, String> {
+    Notification::new()
+        .summary(title)
+        .body(text)
+        .icon(icon_path.unwrap_or(
+            "/usr/share/icons/Papirus-Dark/symbolic/status/weather-severe-alert-symbolic.svg",
+        ))
+        .appname("National Weather Service Daemon")
+        .urgency(Urgency::Critical)
+        .timeout(Timeout::Never)
+        .hint(Hint::Resident(true))
+        .action("acknowledge", "Acknowledge")
+        .show()
+        .map_err(|e| {
+            format!(
+                "Could not show the emergency alert, no dialog could be shown and the notification failed: {}",
+                e
+            )
+        })
+}
+
+fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
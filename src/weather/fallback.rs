@@ -0,0 +1,180 @@
+use std::{
+    fmt::Display,
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+    str::FromStr,
+    thread::JoinHandle,
+};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    daemon::{metrics::METRICS, Config},
+    print_error,
+    weather::{
+        geometry::Proximity,
+        weather::{play_alert_audio, send_notification, AlertProperties},
+    },
+};
+
+/// Where alerts go when the desktop notification can't be shown. Configured as `stdout`, `wall`
+/// to message every terminal with wall(1), `file:<path>` to append to a file, or `none` to only
+/// retry on the next check
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(try_from = "String", into = "String")]
+pub enum FallbackSink {
+    /// The alert isn't acknowledged and is tried again on the next check
+    #[default]
+    Disabled,
+    /// Only useful when stdout is read by someone, the daemon already prints every alert there
+    Stdout,
+    Wall,
+    File(PathBuf),
+}
+
+impl FallbackSink {
+    /// The sink's name in the notification metrics
+    pub fn get_label(&self) -> &'static str {
+        match self {
+            FallbackSink::Disabled => "none",
+            FallbackSink::Stdout => "stdout",
+            FallbackSink::Wall => "wall",
+            FallbackSink::File(_) => "file",
+        }
+    }
+}
+
+/// Sends the notification for an alert, or delivers it to `notification_fallback` when the
+/// notification server can't be reached. Errors when the alert couldn't be delivered anywhere.
+/// Blocks while a notification that can't be shown is retried
+pub fn send_alert(
+    alert_properties: &AlertProperties,
+    proximity: Option<&Proximity>,
+    config: &Config,
+) -> Result<Option<JoinHandle<()>>, String> {
+    let error = match send_notification(alert_properties, proximity, config) {
+        Ok(handle) => return Ok(handle),
+        Err(e) => e,
+    };
+    let sink = &config.notification_fallback;
+    if *sink == FallbackSink::Disabled {
+        return Err(error);
+    }
+
+    print_error!("{}, sending the alert to {} instead", error, sink);
+    send_fallback(alert_properties, proximity, sink)
+        .map_err(|e| format!("{}, and the fallback failed: {}", error, e))?;
+    METRICS.record_notification(
+        sink.get_label(),
+        &format!("{:?}", alert_properties.severity),
+    );
+    play_alert_audio(alert_properties, proximity, config, true);
+    Ok(None)
+}
+
+/// Delivers an alert to the fallback sink as plain text
+pub fn send_fallback(
+    alert_properties: &AlertProperties,
+    proximity: Option<&Proximity>,
+    sink: &FallbackSink,
+) -> Result<(), String> {
+    let mut text = format!(
+        "{:?} Weather Alert: {}",
+        alert_properties.severity, alert_properties.headline
+    );
+    if let Some(proximity) = proximity {
+        text = format!("{}\n{} {}", text, alert_properties.event, proximity);
+    }
+    if let Some(times) = alert_properties.get_time_summary() {
+        text = format!("{}\n{}", text, times);
+    }
+    if let Some(instruction) = &alert_properties.instruction {
+        text = format!("{}\n{}", text, instruction);
+    }
+
+    match sink {
+        FallbackSink::Disabled => Err("The notification fallback is disabled".to_string()),
+        FallbackSink::Stdout => {
+            println!("{}", text);
+            Ok(())
+        }
+        FallbackSink::Wall => {
+            // wall reads the message from stdin when it isn't given as an argument
+            let mut wall = Command::new("wall")
+                .stdin(Stdio::piped())
+                .spawn()
+                .map_err(|e| format!("Could not run wall: {}", e))?;
+            if let Some(mut stdin) = wall.stdin.take() {
+                stdin
+                    .write_all(text.as_bytes())
+                    .map_err(|e| format!("Could not write to wall: {}", e))?;
+            }
+            match wall.wait() {
+                Ok(status) if status.success() => Ok(()),
+                Ok(status) => Err(format!("wall exited with {}", status)),
+                Err(e) => Err(format!("Could not run wall: {}", e)),
+            }
+        }
+        FallbackSink::File(path) => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| {
+                writeln!(
+                    file,
+                    "[{}] {}\n",
+                    Local::now().format("%Y-%m-%d %H:%M:%S"),
+                    text
+                )
+            })
+            .map_err(|e| format!("Could not write alert to {}: {}", path.to_string_lossy(), e)),
+    }
+}
+
+impl FromStr for FallbackSink {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(FallbackSink::Disabled),
+            "stdout" => Ok(FallbackSink::Stdout),
+            "wall" => Ok(FallbackSink::Wall),
+            _ => match s.strip_prefix("file:") {
+                Some("") => Err("Fallback file path can't be empty".to_string()),
+                Some(path) => Ok(FallbackSink::File(PathBuf::from(path))),
+                None => Err(format!(
+                    "Unknown notification fallback {:?}, expected stdout, wall, file:<path> or none",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for FallbackSink {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<FallbackSink> for String {
+    fn from(sink: FallbackSink) -> Self {
+        sink.to_string()
+    }
+}
+
+impl Display for FallbackSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FallbackSink::Disabled => write!(f, "none"),
+            FallbackSink::Stdout => write!(f, "stdout"),
+            FallbackSink::Wall => write!(f, "wall"),
+            FallbackSink::File(path) => write!(f, "file:{}", path.to_string_lossy()),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+
+use crate::weather::{
+    parameters::{DamageThreat, TornadoDetection},
+    weather::{get_level_for_severity, AlertProperties, Event, Severity},
+};
+
+/// Narrows down which alerts are notified. A filter applies to alerts of its `event` (or every
+/// alert when no event is given), and an applicable alert is only notified when it meets all of
+/// the filter's conditions. Alerts that no filter applies to are always notified
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AlertFilter {
+    pub event: Option<Event>,
+    pub min_severity: Option<Severity>,
+    /// At least this certain, e.g. `RADAR INDICATED` also matches `OBSERVED`
+    pub tornado_detection: Option<TornadoDetection>,
+    pub tornado_damage_threat: Option<DamageThreat>,
+    pub thunderstorm_damage_threat: Option<DamageThreat>,
+    /// Inches
+    pub min_hail_size: Option<f64>,
+    /// Miles per hour
+    pub min_wind_gust: Option<f64>,
+}
+
+impl AlertFilter {
+    pub fn applies_to(&self, alert_properties: &AlertProperties) -> bool {
+        match &self.event {
+            Some(event) => *event == alert_properties.event,
+            None => true,
+        }
+    }
+
+    pub fn matches(&self, alert_properties: &AlertProperties) -> bool {
+        let parameters = &alert_properties.parameters;
+
+        let at_least = |required: Option<f64>, actual: Option<f64>| match required {
+            Some(required) => actual.is_some_and(|actual| actual >= required),
+            None => true,
+        };
+
+        let severity_matches = match &self.min_severity {
+            Some(severity) => {
+                get_level_for_severity(&alert_properties.severity)
+                    >= get_level_for_severity(severity)
+            }
+            None => true,
+        };
+
+        severity_matches
+            && at_least_tag(&self.tornado_detection, &parameters.tornado_detection)
+            && at_least_tag(
+                &self.tornado_damage_threat,
+                &parameters.tornado_damage_threat,
+            )
+            && at_least_tag(
+                &self.thunderstorm_damage_threat,
+                &parameters.thunderstorm_damage_threat,
+            )
+            && at_least(self.min_hail_size, parameters.max_hail_size)
+            && at_least(self.min_wind_gust, parameters.max_wind_gust)
+    }
+}
+
+fn at_least_tag<T: PartialOrd>(required: &Option<T>, actual: &Option<T>) -> bool {
+    match (required, actual) {
+        (Some(required), Some(actual)) => actual >= required,
+        (Some(_), None) => false,
+        (None, _) => true,
+    }
+}
+
+/// Whether an alert passes the configured filters
+pub fn should_notify(alert_properties: &AlertProperties, filters: &[AlertFilter]) -> bool {
+    let mut applicable = filters
+        .iter()
+        .filter(|filter| filter.applies_to(alert_properties))
+        .peekable();
+
+    if applicable.peek().is_none() {
+        return true;
+    }
+
+    applicable.any(|filter| filter.matches(alert_properties))
+}
@@ -0,0 +1,273 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, FixedOffset, Local};
+use notify_rust::{Notification, Urgency};
+use serde::Deserialize;
+
+use crate::{
+    daemon::{Config, Location},
+    print_error,
+    weather::weather::fetch_text,
+};
+
+#[derive(Debug, Deserialize)]
+struct Point {
+    properties: PointProperties,
+}
+
+/// The gridpoint metadata for a location, pointing at its forecast and observation endpoints
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PointProperties {
+    pub grid_id: String,
+    pub grid_x: i64,
+    pub grid_y: i64,
+    pub forecast: String,
+    pub forecast_hourly: String,
+    pub observation_stations: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Forecast {
+    properties: ForecastProperties,
+}
+
+#[derive(Debug, Deserialize)]
+struct ForecastProperties {
+    periods: Vec<ForecastPeriod>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ForecastPeriod {
+    /// Empty for hourly periods, otherwise something like "Tonight" or "Thursday"
+    pub name: String,
+    pub start_time: DateTime<FixedOffset>,
+    pub end_time: DateTime<FixedOffset>,
+    pub temperature: i32,
+    pub temperature_unit: String,
+    #[serde(default)]
+    pub probability_of_precipitation: Option<QuantitativeValue>,
+    pub wind_speed: String,
+    pub wind_direction: String,
+    pub short_forecast: String,
+    pub detailed_forecast: String,
+}
+
+/// A measurement with a WMO unit code such as `wmoUnit:degC`
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QuantitativeValue {
+    pub value: Option<f64>,
+    #[serde(default)]
+    pub unit_code: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Stations {
+    features: Vec<Station>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Station {
+    properties: StationProperties,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StationProperties {
+    station_identifier: String,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ObservationResponse {
+    properties: Observation,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Observation {
+    pub timestamp: DateTime<FixedOffset>,
+    pub text_description: String,
+    pub temperature: QuantitativeValue,
+    pub wind_speed: QuantitativeValue,
+    pub relative_humidity: QuantitativeValue,
+}
+
+/// The latest observation from the station nearest to a location
+#[derive(Debug)]
+pub struct CurrentConditions {
+    pub station: String,
+    pub observation: Observation,
+}
+
+pub fn get_point_url(location: &Location) -> String {
+    format!(
+        "https://api.weather.gov/points/{:.4},{:.4}",
+        location.lat, location.lon
+    )
+}
+
+pub fn fetch_point(config: &Config, location: &Location) -> Result<PointProperties, String> {
+    let text = fetch_text(config, &get_point_url(location))?;
+    let point: Point = serde_json::from_str(&text)
+        .map_err(|e| format!("Failed to deserialize point JSON: {}", e))?;
+    Ok(point.properties)
+}
+
+pub fn fetch_forecast(
+    config: &Config,
+    point: &PointProperties,
+    hourly: bool,
+) -> Result<Vec<ForecastPeriod>, String> {
+    let url = match hourly {
+        true => &point.forecast_hourly,
+        false => &point.forecast,
+    };
+    let text = fetch_text(config, url)?;
+    let forecast: Forecast = serde_json::from_str(&text)
+        .map_err(|e| format!("Failed to deserialize forecast JSON: {}", e))?;
+    Ok(forecast.properties.periods)
+}
+
+/// Fetches the latest observation from the first (nearest) station for a point
+pub fn fetch_current_conditions(
+    config: &Config,
+    point: &PointProperties,
+) -> Result<CurrentConditions, String> {
+    let text = fetch_text(config, &point.observation_stations)?;
+    let stations: Stations = serde_json::from_str(&text)
+        .map_err(|e| format!("Failed to deserialize stations JSON: {}", e))?;
+    let station = match stations.features.first() {
+        Some(station) => &station.properties,
+        None => return Err("No observation stations near this location".to_string()),
+    };
+
+    let text = fetch_text(
+        config,
+        &format!(
+            "https://api.weather.gov/stations/{}/observations/latest",
+            station.station_identifier
+        ),
+    )?;
+    let observation: ObservationResponse = serde_json::from_str(&text)
+        .map_err(|e| format!("Failed to deserialize observation JSON: {}", e))?;
+
+    Ok(CurrentConditions {
+        station: station.name.clone(),
+        observation: observation.properties,
+    })
+}
+
+/// Converts a temperature measurement to fahrenheit
+pub fn get_fahrenheit(value: &QuantitativeValue) -> Option<f64> {
+    let degrees = value.value?;
+    match value.unit_code.as_str() {
+        "wmoUnit:degC" => Some(degrees * 9.0 / 5.0 + 32.0),
+        _ => Some(degrees),
+    }
+}
+
+/// Converts a speed measurement to miles per hour
+pub fn get_mph(value: &QuantitativeValue) -> Option<f64> {
+    let speed = value.value?;
+    match value.unit_code.as_str() {
+        "wmoUnit:km_h-1" => Some(speed / 1.609344),
+        "wmoUnit:m_s-1" => Some(speed * 2.236936),
+        _ => Some(speed),
+    }
+}
+
+impl Display for ForecastPeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Hourly periods don't have names, so label them by their start time
+        let name = match self.name.is_empty() {
+            true => self
+                .start_time
+                .with_timezone(&Local)
+                .format("%a %l %p")
+                .to_string(),
+            false => self.name.clone(),
+        };
+
+        write!(
+            f,
+            "{}: {}\u{00B0}{}, {}",
+            name, self.temperature, self.temperature_unit, self.short_forecast
+        )?;
+
+        if let Some(precipitation) = self
+            .probability_of_precipitation
+            .as_ref()
+            .and_then(|p| p.value)
+        {
+            write!(f, " ({:.0}% chance of precipitation)", precipitation)?;
+        }
+
+        if !self.wind_speed.is_empty() {
+            write!(f, ", wind {} {}", self.wind_direction, self.wind_speed)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for CurrentConditions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at {}",
+            self.observation.text_description, self.station
+        )?;
+        if let Some(temperature) = get_fahrenheit(&self.observation.temperature) {
+            write!(f, ", {:.0}\u{00B0}F", temperature)?;
+        }
+        if let Some(humidity) = self.observation.relative_humidity.value {
+            write!(f, ", {:.0}% humidity", humidity)?;
+        }
+        if let Some(wind) = get_mph(&self.observation.wind_speed) {
+            write!(f, ", wind {:.0} mph", wind)?;
+        }
+        write!(
+            f,
+            " (as of {})",
+            self.observation
+                .timestamp
+                .with_timezone(&Local)
+                .format("%l:%M %p")
+        )
+    }
+}
+
+/// Sends the next two forecast periods (usually today and tonight) as a notification
+pub fn send_forecast_notification(
+    periods: &[ForecastPeriod],
+    location: &Location,
+    config: &Config,
+) {
+    let icon_path = match &config.notification_icon_path {
+        Some(a) => a,
+        None => "/usr/share/icons/Papirus-Dark/symbolic/status/weather-few-clouds-symbolic.svg",
+    };
+
+    let body = periods
+        .iter()
+        .take(2)
+        .map(|period| match config.detailed_notification {
+            true => format!("{}: {}", period.name, period.detailed_forecast),
+            false => period.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    if let Err(e) = Notification::new()
+        .summary(format!("Forecast for {}", location.name).as_str())
+        .body(&body)
+        .icon(icon_path)
+        .appname("National Weather Service Daemon")
+        .urgency(Urgency::Low)
+        .show()
+    {
+        print_error!("Could not send forecast notification: {}", e);
+    }
+}
@@ -0,0 +1,271 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::daemon::Location;
+
+/// A GeoJSON position, longitude first
+pub type Position = [f64; 2];
+
+/// A ring of positions where the first and last position are the same
+pub type Ring = Vec<Position>;
+
+const EARTH_RADIUS_MILES: f64 = 3958.8;
+
+/// How far and in which direction the nearest edge of an alert's polygon is from a location
+#[derive(Debug, Serialize, Clone)]
+pub struct Proximity {
+    pub location: String,
+    pub miles: f64,
+    /// Degrees clockwise from north
+    pub bearing: f64,
+}
+
+/// The GeoJSON geometry of an alert. Storm-based warnings have a polygon, zone-based alerts
+/// usually have no geometry at all
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum Geometry {
+    /// The first ring is the outer boundary, any others are holes
+    Polygon {
+        coordinates: Vec<Ring>,
+    },
+    MultiPolygon {
+        coordinates: Vec<Vec<Ring>>,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+impl Geometry {
+    /// Whether a location falls inside the geometry. Unsupported geometry is treated as
+    /// containing every location so alerts are never hidden because of it
+    pub fn contains(&self, location: &Location) -> bool {
+        let point = [location.lon, location.lat];
+        match self {
+            Geometry::Polygon { coordinates } => polygon_contains(coordinates, point),
+            Geometry::MultiPolygon { coordinates } => coordinates
+                .iter()
+                .any(|polygon| polygon_contains(polygon, point)),
+            Geometry::Unsupported => true,
+        }
+    }
+
+    /// The distance and bearing from a location to the nearest edge of the geometry, or `None` if
+    /// the location is inside it or the geometry is unsupported
+    pub fn proximity(&self, location: &Location) -> Option<Proximity> {
+        if self.contains(location) {
+            return None;
+        }
+
+        let rings: Vec<&Ring> = match self {
+            Geometry::Polygon { coordinates } => coordinates.iter().collect(),
+            Geometry::MultiPolygon { coordinates } => coordinates.iter().flatten().collect(),
+            Geometry::Unsupported => return None,
+        };
+
+        // Project onto a flat plane in miles centered on the location. Warning polygons are small
+        // enough that the distortion doesn't matter
+        let scale_x = EARTH_RADIUS_MILES * location.lat.to_radians().cos();
+        let project = |position: &Position| {
+            [
+                (position[0] - location.lon).to_radians() * scale_x,
+                (position[1] - location.lat).to_radians() * EARTH_RADIUS_MILES,
+            ]
+        };
+
+        let nearest = rings
+            .iter()
+            .flat_map(|ring| ring.windows(2))
+            .map(|edge| nearest_point_on_segment(project(&edge[0]), project(&edge[1])))
+            .min_by(|a, b| length(*a).total_cmp(&length(*b)))?;
+
+        Some(Proximity {
+            location: location.name.clone(),
+            miles: length(nearest),
+            bearing: nearest[0].atan2(nearest[1]).to_degrees().rem_euclid(360.0),
+        })
+    }
+}
+
+/// The point on the segment from `a` to `b` closest to the origin
+fn nearest_point_on_segment(a: Position, b: Position) -> Position {
+    let direction = [b[0] - a[0], b[1] - a[1]];
+    let length_squared = direction[0].powi(2) + direction[1].powi(2);
+    if length_squared == 0.0 {
+        return a;
+    }
+    let t = (-(a[0] * direction[0] + a[1] * direction[1]) / length_squared).clamp(0.0, 1.0);
+    [a[0] + t * direction[0], a[1] + t * direction[1]]
+}
+
+fn length(position: Position) -> f64 {
+    (position[0].powi(2) + position[1].powi(2)).sqrt()
+}
+
+/// The 16 point compass direction for a bearing in degrees
+pub fn get_compass_direction(bearing: f64) -> &'static str {
+    const DIRECTIONS: [&str; 16] = [
+        "N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE", "S", "SSW", "SW", "WSW", "W", "WNW",
+        "NW", "NNW",
+    ];
+    DIRECTIONS[((bearing.rem_euclid(360.0) / 22.5).round() as usize) % 16]
+}
+
+impl Display for Proximity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.0} mi {} of {}",
+            self.miles,
+            get_compass_direction(self.bearing),
+            self.location
+        )
+    }
+}
+
+/// Points on the edge of the polygon, including the edge of a hole, count as inside it
+fn polygon_contains(rings: &[Ring], point: Position) -> bool {
+    match rings.split_first() {
+        Some((outer, holes)) => {
+            (ring_contains(outer, point) || is_on_ring(outer, point))
+                && !holes
+                    .iter()
+                    .any(|hole| ring_contains(hole, point) && !is_on_ring(hole, point))
+        }
+        None => false,
+    }
+}
+
+/// Whether a point lies on one of the ring's edges, within rounding error
+fn is_on_ring(ring: &[Position], point: Position) -> bool {
+    const TOLERANCE: f64 = 1e-9;
+    ring.windows(2).any(|edge| {
+        let relative = |position: Position| [position[0] - point[0], position[1] - point[1]];
+        let nearest = nearest_point_on_segment(relative(edge[0]), relative(edge[1]));
+        length(nearest) <= TOLERANCE
+    })
+}
+
+/// Ray casting point in polygon test
+fn ring_contains(ring: &[Position], point: Position) -> bool {
+    let [x, y] = point;
+    let mut inside = false;
+    let mut previous = match ring.last() {
+        Some(position) => *position,
+        None => return false,
+    };
+
+    for current in ring {
+        let [x1, y1] = *current;
+        let [x2, y2] = previous;
+        if (y1 > y) != (y2 > y) && x < (x2 - x1) * (y - y1) / (y2 - y1) + x1 {
+            inside = !inside;
+        }
+        previous = *current;
+    }
+
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(lat: f64, lon: f64) -> Location {
+        Location {
+            name: "Home".to_string(),
+            lat,
+            lon,
+        }
+    }
+
+    /// A ring around a rectangle, longitude first like GeoJSON
+    fn rectangle(min_lon: f64, min_lat: f64, max_lon: f64, max_lat: f64) -> Ring {
+        vec![
+            [min_lon, min_lat],
+            [max_lon, min_lat],
+            [max_lon, max_lat],
+            [min_lon, max_lat],
+            [min_lon, min_lat],
+        ]
+    }
+
+    fn warning_polygon() -> Geometry {
+        Geometry::Polygon {
+            coordinates: vec![rectangle(-98.0, 35.0, -97.0, 36.0)],
+        }
+    }
+
+    #[test]
+    fn point_inside_polygon() {
+        let polygon = warning_polygon();
+        assert!(polygon.contains(&location(35.5, -97.5)));
+        assert!(polygon.proximity(&location(35.5, -97.5)).is_none());
+    }
+
+    #[test]
+    fn point_outside_polygon() {
+        let polygon = warning_polygon();
+        let home = location(35.5, -96.5);
+        assert!(!polygon.contains(&home));
+
+        // About 28 miles east of the polygon's eastern edge, which lies to the west
+        let proximity = polygon.proximity(&home).unwrap();
+        assert!((proximity.miles - 28.1).abs() < 0.5, "{}", proximity.miles);
+        assert_eq!(get_compass_direction(proximity.bearing), "W");
+    }
+
+    #[test]
+    fn point_on_polygon_edge_is_inside() {
+        let polygon = warning_polygon();
+        for home in [
+            location(35.5, -98.0),
+            location(35.5, -97.0),
+            location(35.0, -97.5),
+            location(36.0, -97.5),
+            location(36.0, -97.0),
+        ] {
+            assert!(polygon.contains(&home), "{:?}", home);
+        }
+    }
+
+    #[test]
+    fn multipolygon_with_hole() {
+        let multipolygon = Geometry::MultiPolygon {
+            coordinates: vec![
+                vec![
+                    rectangle(-98.0, 35.0, -97.0, 36.0),
+                    rectangle(-97.75, 35.25, -97.25, 35.75),
+                ],
+                vec![rectangle(-96.0, 35.0, -95.0, 36.0)],
+            ],
+        };
+
+        // Between the outer ring and the hole
+        assert!(multipolygon.contains(&location(35.1, -97.9)));
+        // In the hole
+        assert!(!multipolygon.contains(&location(35.5, -97.5)));
+        assert!(multipolygon.proximity(&location(35.5, -97.5)).is_some());
+        // On the edge of the hole
+        assert!(multipolygon.contains(&location(35.5, -97.75)));
+        // In the second polygon
+        assert!(multipolygon.contains(&location(35.5, -95.5)));
+        // Between the polygons
+        assert!(!multipolygon.contains(&location(35.5, -96.5)));
+    }
+
+    #[test]
+    fn deserializes_geojson() {
+        let geometry: Geometry = serde_json::from_str(
+            r#"{"type": "Polygon", "coordinates": [[[-98.0, 35.0], [-97.0, 35.0], [-97.0, 36.0], [-98.0, 35.0]]]}"#,
+        )
+        .unwrap();
+        assert!(matches!(geometry, Geometry::Polygon { .. }));
+
+        let geometry: Geometry =
+            serde_json::from_str(r#"{"type": "Point", "coordinates": [-97.0, 35.0]}"#).unwrap();
+        assert!(matches!(geometry, Geometry::Unsupported));
+        assert!(geometry.contains(&location(0.0, 0.0)));
+    }
+}
@@ -1 +1,13 @@
+pub mod emergency;
+pub mod fallback;
+pub mod filters;
+pub mod forecast;
+pub mod geometry;
+pub mod outlook;
+pub mod parameters;
+pub mod rules;
+pub mod sound;
+pub mod speech;
+pub mod vtec;
+#[allow(clippy::module_inception)]
 pub mod weather;
@@ -0,0 +1,88 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    daemon::{Config, Location},
+    weather::{geometry::Geometry, weather::fetch_text},
+};
+
+const SPC_DAY_1_OUTLOOK_URL: &str =
+    "https://www.spc.noaa.gov/products/outlook/day1otlk_cat.lyr.geojson";
+
+/// A Storm Prediction Center categorical convective outlook risk, in increasing order
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum SpcRisk {
+    Thunderstorm,
+    Marginal,
+    Slight,
+    Enhanced,
+    Moderate,
+    High,
+}
+
+impl SpcRisk {
+    /// Reads the `LABEL` of an outlook area
+    fn from_label(label: &str) -> Option<Self> {
+        match label {
+            "TSTM" => Some(SpcRisk::Thunderstorm),
+            "MRGL" => Some(SpcRisk::Marginal),
+            "SLGT" => Some(SpcRisk::Slight),
+            "ENH" => Some(SpcRisk::Enhanced),
+            "MDT" => Some(SpcRisk::Moderate),
+            "HIGH" => Some(SpcRisk::High),
+            _ => None,
+        }
+    }
+}
+
+impl Display for SpcRisk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpcRisk::Thunderstorm => write!(f, "General Thunderstorms"),
+            SpcRisk::Marginal => write!(f, "Marginal Risk"),
+            SpcRisk::Slight => write!(f, "Slight Risk"),
+            SpcRisk::Enhanced => write!(f, "Enhanced Risk"),
+            SpcRisk::Moderate => write!(f, "Moderate Risk"),
+            SpcRisk::High => write!(f, "High Risk"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Outlook {
+    features: Vec<OutlookArea>,
+}
+
+#[derive(Deserialize)]
+struct OutlookArea {
+    properties: OutlookAreaProperties,
+    #[serde(default)]
+    geometry: Option<Geometry>,
+}
+
+#[derive(Deserialize)]
+struct OutlookAreaProperties {
+    #[serde(rename = "LABEL")]
+    label: String,
+}
+
+/// The highest day 1 convective outlook risk any of the locations is in, `None` when they are all
+/// outside of the outlook areas
+pub fn fetch_spc_risk(config: &Config, locations: &[Location]) -> Result<Option<SpcRisk>, String> {
+    let text = fetch_text(config, SPC_DAY_1_OUTLOOK_URL)?;
+    let outlook: Outlook = serde_json::from_str(&text)
+        .map_err(|e| format!("Could not parse the SPC outlook: {}", e))?;
+
+    Ok(outlook
+        .features
+        .iter()
+        .filter(|area| match &area.geometry {
+            // Unsupported geometry would contain every location
+            Some(Geometry::Unsupported) | None => false,
+            Some(geometry) => locations.iter().any(|location| geometry.contains(location)),
+        })
+        .filter_map(|area| SpcRisk::from_label(&area.properties.label))
+        .max())
+}
@@ -0,0 +1,362 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+
+use crate::weather::{geometry::get_compass_direction, vtec::Vtec};
+
+/// The hazard tags NWS attaches to an alert's `parameters`. The API sends every parameter as a
+/// list of strings, values that can't be parsed are dropped. They are serialized back the same
+/// way so they can be read again
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+#[serde(
+    from = "HashMap<String, serde_json::Value>",
+    into = "HashMap<String, Vec<String>>"
+)]
+pub struct AlertParameters {
+    pub event_motion: Option<EventMotion>,
+    /// Inches
+    pub max_hail_size: Option<f64>,
+    /// Miles per hour
+    pub max_wind_gust: Option<f64>,
+    pub tornado_detection: Option<TornadoDetection>,
+    pub tornado_damage_threat: Option<DamageThreat>,
+    pub thunderstorm_damage_threat: Option<DamageThreat>,
+    /// P-VTEC codes, one for each event the product is about
+    pub vtec: Vec<Vtec>,
+}
+
+/// Where a storm was and where it is heading, from `eventMotionDescription`
+#[derive(Debug, Serialize, Clone)]
+pub struct EventMotion {
+    pub time: DateTime<FixedOffset>,
+    /// Degrees the storm is moving from
+    pub direction: f64,
+    pub speed_knots: f64,
+    /// Latitude and longitude of the storm
+    pub positions: Vec<(f64, f64)>,
+}
+
+/// How a tornado was detected, ordered from least to most certain
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, PartialOrd)]
+pub enum TornadoDetection {
+    #[serde(rename = "POSSIBLE")]
+    Possible,
+    #[serde(rename = "RADAR INDICATED")]
+    RadarIndicated,
+    #[serde(rename = "OBSERVED")]
+    Observed,
+}
+
+/// Impact based warning damage tags, ordered from least to most severe
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, PartialOrd)]
+pub enum DamageThreat {
+    #[serde(rename = "CONSIDERABLE")]
+    Considerable,
+    #[serde(rename = "DESTRUCTIVE")]
+    Destructive,
+    #[serde(rename = "CATASTROPHIC")]
+    Catastrophic,
+}
+
+impl From<HashMap<String, Vec<String>>> for AlertParameters {
+    fn from(parameters: HashMap<String, Vec<String>>) -> Self {
+        let first = |key: &str| parameters.get(key).and_then(|values| values.first());
+
+        AlertParameters {
+            event_motion: first("eventMotionDescription").and_then(|v| v.parse().ok()),
+            max_hail_size: first("maxHailSize").and_then(|v| parse_leading_number(v)),
+            max_wind_gust: first("maxWindGust").and_then(|v| parse_leading_number(v)),
+            tornado_detection: first("tornadoDetection").and_then(|v| v.parse().ok()),
+            tornado_damage_threat: first("tornadoDamageThreat").and_then(|v| v.parse().ok()),
+            thunderstorm_damage_threat: first("thunderstormDamageThreat")
+                .and_then(|v| v.parse().ok()),
+            vtec: parameters
+                .get("VTEC")
+                .map(|codes| codes.iter().filter_map(|code| code.parse().ok()).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl From<HashMap<String, serde_json::Value>> for AlertParameters {
+    fn from(parameters: HashMap<String, serde_json::Value>) -> Self {
+        // A parameter that isn't a list of strings is skipped instead of failing the whole alert
+        let parameters: HashMap<String, Vec<String>> = parameters
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let values = match value {
+                    serde_json::Value::Array(values) => values
+                        .iter()
+                        .filter_map(|value| value.as_str().map(str::to_string))
+                        .collect(),
+                    serde_json::Value::String(value) => vec![value],
+                    _ => return None,
+                };
+                Some((key, values))
+            })
+            .collect();
+        AlertParameters::from(parameters)
+    }
+}
+
+impl From<AlertParameters> for HashMap<String, Vec<String>> {
+    fn from(parameters: AlertParameters) -> Self {
+        let mut values = HashMap::new();
+        let mut insert = |key: &str, value: Option<String>| {
+            if let Some(value) = value {
+                values.insert(key.to_string(), vec![value]);
+            }
+        };
+
+        insert(
+            "eventMotionDescription",
+            parameters.event_motion.map(|v| v.get_description()),
+        );
+        insert(
+            "maxHailSize",
+            parameters.max_hail_size.map(|v| format!("{:.2}", v)),
+        );
+        insert(
+            "maxWindGust",
+            parameters.max_wind_gust.map(|v| format!("{:.0} MPH", v)),
+        );
+        insert(
+            "tornadoDetection",
+            parameters
+                .tornado_detection
+                .map(|v| v.to_string().to_uppercase()),
+        );
+        insert(
+            "tornadoDamageThreat",
+            parameters
+                .tornado_damage_threat
+                .map(|v| v.to_string().to_uppercase()),
+        );
+        insert(
+            "thunderstormDamageThreat",
+            parameters
+                .thunderstorm_damage_threat
+                .map(|v| v.to_string().to_uppercase()),
+        );
+        if !parameters.vtec.is_empty() {
+            values.insert(
+                "VTEC".to_string(),
+                parameters
+                    .vtec
+                    .iter()
+                    .map(|vtec| vtec.to_string())
+                    .collect(),
+            );
+        }
+        values
+    }
+}
+
+/// Parses values like "1.00", "Up to .75" or "60 MPH"
+fn parse_leading_number(value: &str) -> Option<f64> {
+    value
+        .split_whitespace()
+        .find_map(|word| word.parse::<f64>().ok())
+}
+
+impl EventMotion {
+    /// The motion in the `eventMotionDescription` format it is parsed from
+    pub fn get_description(&self) -> String {
+        let positions: Vec<String> = self
+            .positions
+            .iter()
+            .map(|(lat, lon)| format!("{},{}", lat, lon))
+            .collect();
+        format!(
+            "{}...storm...{:03.0}DEG...{:.0}KT...{}",
+            self.time.to_rfc3339(),
+            self.direction,
+            self.speed_knots,
+            positions.join(" ")
+        )
+    }
+}
+
+impl FromStr for EventMotion {
+    type Err = String;
+
+    /// Parses `2024-05-06T23:04:00-00:00...storm...238DEG...39KT...34.84,-97.93 34.9,-97.8`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split("...").collect();
+        if parts.len() < 5 {
+            return Err(format!("Unexpected event motion description {}", s));
+        }
+
+        let time = DateTime::parse_from_rfc3339(parts[0]).map_err(|e| e.to_string())?;
+        let direction = parts[2]
+            .trim_end_matches("DEG")
+            .parse::<f64>()
+            .map_err(|e| e.to_string())?;
+        let speed_knots = parts[3]
+            .trim_end_matches("KT")
+            .parse::<f64>()
+            .map_err(|e| e.to_string())?;
+        let positions = parts[4]
+            .split_whitespace()
+            .filter_map(|position| {
+                let (lat, lon) = position.split_once(',')?;
+                Some((lat.parse().ok()?, lon.parse().ok()?))
+            })
+            .collect();
+
+        Ok(EventMotion {
+            time,
+            direction,
+            speed_knots,
+            positions,
+        })
+    }
+}
+
+impl FromStr for TornadoDetection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "POSSIBLE" => Ok(TornadoDetection::Possible),
+            "RADAR INDICATED" => Ok(TornadoDetection::RadarIndicated),
+            "OBSERVED" => Ok(TornadoDetection::Observed),
+            _ => Err(format!("Unknown tornado detection {}", s)),
+        }
+    }
+}
+
+impl FromStr for DamageThreat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "CONSIDERABLE" => Ok(DamageThreat::Considerable),
+            "DESTRUCTIVE" => Ok(DamageThreat::Destructive),
+            "CATASTROPHIC" => Ok(DamageThreat::Catastrophic),
+            _ => Err(format!("Unknown damage threat {}", s)),
+        }
+    }
+}
+
+impl AlertParameters {
+    /// A one line summary of the hazard tags, `None` if the alert doesn't have any
+    pub fn summary(&self) -> Option<String> {
+        let mut parts: Vec<String> = vec![];
+
+        if let Some(detection) = &self.tornado_detection {
+            parts.push(format!("Tornado {}", detection));
+        }
+        if let Some(threat) = &self.tornado_damage_threat {
+            parts.push(format!("{} tornado damage threat", threat));
+        }
+        if let Some(threat) = &self.thunderstorm_damage_threat {
+            parts.push(format!("{} thunderstorm damage threat", threat));
+        }
+        if let Some(hail) = self.max_hail_size.filter(|hail| *hail > 0.0) {
+            parts.push(format!("Hail up to {:.2} in", hail));
+        }
+        if let Some(gust) = self.max_wind_gust {
+            parts.push(format!("Wind gusts up to {:.0} mph", gust));
+        }
+        if let Some(motion) = &self.event_motion {
+            parts.push(motion.to_string());
+        }
+
+        match parts.is_empty() {
+            true => None,
+            false => Some(parts.join(", ")),
+        }
+    }
+}
+
+impl Display for EventMotion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.speed_knots == 0.0 {
+            return write!(f, "Stationary");
+        }
+        // The description gives the direction the storm is coming from
+        write!(
+            f,
+            "Moving {} at {:.0} mph",
+            get_compass_direction(self.direction + 180.0),
+            self.speed_knots * 1.150779
+        )
+    }
+}
+
+impl Display for TornadoDetection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let readable = match self {
+            TornadoDetection::Possible => "possible",
+            TornadoDetection::RadarIndicated => "radar indicated",
+            TornadoDetection::Observed => "observed",
+        };
+        write!(f, "{}", readable)
+    }
+}
+
+impl Display for DamageThreat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let readable = match self {
+            DamageThreat::Considerable => "Considerable",
+            DamageThreat::Destructive => "Destructive",
+            DamageThreat::Catastrophic => "Catastrophic",
+        };
+        write!(f, "{}", readable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parameters_round_trip() {
+        let json = r#"{
+            "eventMotionDescription": ["2024-05-06T23:04:00-00:00...storm...238DEG...39KT...34.84,-97.93 34.9,-97.8"],
+            "maxHailSize": ["1.75"],
+            "maxWindGust": ["60 MPH"],
+            "tornadoDetection": ["RADAR INDICATED"],
+            "tornadoDamageThreat": ["CONSIDERABLE"],
+            "VTEC": ["/O.NEW.KOUN.TO.W.0045.240506T2304Z-240506T2345Z/"],
+            "WMOidentifier": ["WFUS54 KOUN 062304"]
+        }"#;
+        let parsed: AlertParameters = serde_json::from_str(json).unwrap();
+        let reparsed: AlertParameters =
+            serde_json::from_str(&serde_json::to_string(&parsed).unwrap()).unwrap();
+
+        assert_eq!(reparsed.max_hail_size, Some(1.75));
+        assert_eq!(reparsed.max_wind_gust, Some(60.0));
+        assert_eq!(
+            reparsed.tornado_detection,
+            Some(TornadoDetection::RadarIndicated)
+        );
+        assert_eq!(
+            reparsed.tornado_damage_threat,
+            Some(DamageThreat::Considerable)
+        );
+        assert_eq!(reparsed.vtec, parsed.vtec);
+        assert_eq!(reparsed.vtec.len(), 1);
+        let motion = reparsed.event_motion.unwrap();
+        assert_eq!(motion.direction, 238.0);
+        assert_eq!(motion.speed_knots, 39.0);
+        assert_eq!(motion.positions, [(34.84, -97.93), (34.9, -97.8)]);
+    }
+
+    #[test]
+    fn parameters_that_are_not_string_lists_are_skipped() {
+        let json = r#"{
+            "maxHailSize": 1.0,
+            "maxWindGust": "70 MPH",
+            "BLOCKCHANNEL": [{"unexpected": true}, "EAS"],
+            "tornadoDetection": null
+        }"#;
+        let parsed: AlertParameters = serde_json::from_str(json).unwrap();
+
+        assert_eq!(parsed.max_hail_size, None);
+        assert_eq!(parsed.max_wind_gust, Some(70.0));
+        assert_eq!(parsed.tornado_detection, None);
+    }
+}
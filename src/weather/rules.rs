@@ -0,0 +1,220 @@
+use chrono::{DateTime, Duration, FixedOffset, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    daemon::Location,
+    weather::{
+        forecast::ForecastPeriod,
+        parameters::AlertParameters,
+        weather::{AlertProperties, Event, Severity},
+    },
+};
+
+/// A personal trigger evaluated against the hourly forecast, e.g. "precipitation above 70% in
+/// the next 3 hours"
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ForecastRule {
+    pub name: String,
+    pub field: RuleField,
+    pub comparison: RuleComparison,
+    pub value: f64,
+    /// How many hours ahead of now to look
+    pub hours: i64,
+    #[serde(default = "default_rule_severity")]
+    pub severity: Severity,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleField {
+    /// Probability of precipitation in percent
+    Precipitation,
+    /// Temperature in the forecast's unit (fahrenheit unless the API says otherwise)
+    Temperature,
+    /// Highest wind speed of the period in mph
+    Wind,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleComparison {
+    Above,
+    Below,
+}
+
+fn default_rule_severity() -> Severity {
+    Severity::Minor
+}
+
+/// Reads the value a rule looks at out of a forecast period
+pub fn get_period_value(period: &ForecastPeriod, field: &RuleField) -> Option<f64> {
+    match field {
+        RuleField::Precipitation => period.probability_of_precipitation.as_ref()?.value,
+        RuleField::Temperature => Some(period.temperature as f64),
+        // Wind speed is text such as "10 mph" or "10 to 15 mph", use the highest number
+        RuleField::Wind => period
+            .wind_speed
+            .split_whitespace()
+            .filter_map(|word| word.parse::<f64>().ok())
+            .reduce(f64::max),
+    }
+}
+
+/// Checks a rule against the hourly forecast, returning a synthetic alert when any period inside
+/// the rule's window matches. The alert id is stable for the day so it is only sent once
+pub fn evaluate_rule(
+    rule: &ForecastRule,
+    periods: &[ForecastPeriod],
+    location: &Location,
+    now: DateTime<Local>,
+) -> Option<AlertProperties> {
+    let window_end = now + Duration::hours(rule.hours);
+
+    let matching: Vec<(&ForecastPeriod, f64)> = periods
+        .iter()
+        .filter(|period| period.end_time > now && period.start_time < window_end)
+        .filter_map(|period| {
+            let value = get_period_value(period, &rule.field)?;
+            let matches = match rule.comparison {
+                RuleComparison::Above => value > rule.value,
+                RuleComparison::Below => value < rule.value,
+            };
+            matches.then_some((period, value))
+        })
+        .collect();
+
+    let (first, first_value) = matching.first()?;
+
+    let description = matching
+        .iter()
+        .map(|(period, value)| {
+            format!(
+                "{}: {} {}",
+                period.start_time.with_timezone(&Local).format("%a %l %p"),
+                describe_value(&rule.field, *value),
+                period.short_forecast
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    Some(AlertProperties {
+        headline: format!(
+            "{} at {}: {} {}",
+            rule.name,
+            location.name,
+            describe_value(&rule.field, *first_value),
+            first.start_time.with_timezone(&Local).format("%a %l %p")
+        ),
+        description,
+        severity: rule.severity.clone(),
+        id: format!(
+            "nwsd:rule:{}:{}:{}",
+            location.name,
+            rule.name,
+            now.date_naive()
+        ),
+        event: Event::ForecastTrigger,
+        instruction: None,
+        parameters: AlertParameters::default(),
+        sent: Some(now.fixed_offset()),
+        effective: Some(now.fixed_offset()),
+        // The headline says when it starts, an onset would get it notified a second time
+        onset: None,
+        // The id changes at midnight, it has to be remembered until then so it isn't sent again
+        expires: Some(get_end_of_day(now)),
+        ends: matching.last().map(|(period, _)| period.end_time),
+        references: Vec::new(),
+    })
+}
+
+/// Midnight after `now`, in the local time zone
+fn get_end_of_day(now: DateTime<Local>) -> DateTime<FixedOffset> {
+    (now.date_naive() + Duration::days(1))
+        .and_hms_opt(0, 0, 0)
+        .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+        .unwrap_or(now + Duration::days(1))
+        .fixed_offset()
+}
+
+fn describe_value(field: &RuleField, value: f64) -> String {
+    match field {
+        RuleField::Precipitation => format!("{:.0}% chance of precipitation", value),
+        RuleField::Temperature => format!("{:.0}\u{00B0}", value),
+        RuleField::Wind => format!("wind {:.0} mph", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weather::forecast::QuantitativeValue;
+
+    fn period(now: DateTime<Local>, hour: i64, precipitation: f64) -> ForecastPeriod {
+        let start = (now + Duration::hours(hour)).fixed_offset();
+        ForecastPeriod {
+            name: String::new(),
+            start_time: start,
+            end_time: start + Duration::hours(1),
+            temperature: 60,
+            temperature_unit: "F".to_string(),
+            probability_of_precipitation: Some(QuantitativeValue {
+                value: Some(precipitation),
+                unit_code: "wmoUnit:percent".to_string(),
+            }),
+            wind_speed: "5 to 10 mph".to_string(),
+            wind_direction: "SW".to_string(),
+            short_forecast: "Showers".to_string(),
+            detailed_forecast: String::new(),
+        }
+    }
+
+    fn rain_rule() -> ForecastRule {
+        ForecastRule {
+            name: "Rain".to_string(),
+            field: RuleField::Precipitation,
+            comparison: RuleComparison::Above,
+            value: 70.0,
+            hours: 3,
+            severity: Severity::Minor,
+        }
+    }
+
+    fn home() -> Location {
+        Location {
+            name: "Home".to_string(),
+            lat: 36.974117,
+            lon: -122.030792,
+        }
+    }
+
+    #[test]
+    fn matching_periods_are_remembered_until_midnight() {
+        let now = Local::now();
+        let periods = [
+            period(now, 0, 20.0),
+            period(now, 1, 80.0),
+            period(now, 2, 90.0),
+        ];
+
+        let alert = evaluate_rule(&rain_rule(), &periods, &home(), now).unwrap();
+
+        assert_eq!(alert.description.lines().count(), 2);
+        assert_eq!(
+            alert.id,
+            format!("nwsd:rule:Home:Rain:{}", now.date_naive())
+        );
+        assert_eq!(alert.ends, Some(periods[2].end_time));
+        let expires = alert.expires.unwrap().with_timezone(&Local);
+        assert_eq!(expires.date_naive(), now.date_naive() + Duration::days(1));
+        assert!(expires > now);
+    }
+
+    #[test]
+    fn periods_outside_the_window_are_ignored() {
+        let now = Local::now();
+        let periods = [period(now, 0, 20.0), period(now, 5, 90.0)];
+
+        assert!(evaluate_rule(&rain_rule(), &periods, &home(), now).is_none());
+    }
+}
@@ -0,0 +1,214 @@
+use std::{
+    f64::consts::PI,
+    fmt::Display,
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    process::Command,
+    str::FromStr,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    daemon::Config,
+    print_error,
+    weather::weather::{AlertProperties, Event},
+};
+
+const TONE_SAMPLE_RATE: u32 = 22050;
+/// The two frequencies the Emergency Alert System attention signal is made of
+const TONE_FREQUENCIES: [f64; 2] = [853.0, 960.0];
+/// Seconds on and off, in order
+const TONE_CADENCE: [(f64, f64); 3] = [(2.0, 0.5), (1.0, 0.5), (1.0, 0.5)];
+
+/// A sound to play with a notification. Configured as `attention` for the built in attention
+/// tone, `theme:<name>` for a sound theme name passed to the notification server, or a path to a
+/// sound file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum Sound {
+    Attention,
+    Theme(String),
+    File(String),
+}
+
+impl FromStr for Sound {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "attention" {
+            return Ok(Sound::Attention);
+        }
+        match s.strip_prefix("theme:") {
+            Some("") => Err("Sound theme name can't be empty".to_string()),
+            Some(name) => Ok(Sound::Theme(name.to_string())),
+            None if s.is_empty() => Err("Sound file path can't be empty".to_string()),
+            None => Ok(Sound::File(s.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for Sound {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Sound> for String {
+    fn from(sound: Sound) -> Self {
+        sound.to_string()
+    }
+}
+
+impl Display for Sound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Sound::Attention => write!(f, "attention"),
+            Sound::Theme(name) => write!(f, "theme:{}", name),
+            Sound::File(path) => write!(f, "{}", path),
+        }
+    }
+}
+
+pub fn default_siren_events() -> Vec<Event> {
+    vec![Event::TornadoWarning, Event::FlashFloodWarning]
+}
+
+/// The sound configured for an alert's event, falling back to the one for its severity
+pub fn get_sound_for_alert<'a>(
+    alert_properties: &AlertProperties,
+    config: &'a Config,
+) -> Option<&'a Sound> {
+    config
+        .event_sounds
+        .get(&alert_properties.event)
+        .or_else(|| config.severity_sounds.get(&alert_properties.severity))
+}
+
+/// How many times to play an alert's sound, more than once for siren events
+pub fn get_repeats_for_alert(alert_properties: &AlertProperties, config: &Config) -> u32 {
+    match config.siren_events.contains(&alert_properties.event) {
+        true => config.siren_repeats.max(1),
+        false => 1,
+    }
+}
+
+/// Plays a sound `repeats` times in the background. Theme sounds are played with
+/// canberra-gtk-play here, pass them as the notification `sound-name` hint when they only need to
+/// play once
+pub fn play_sound(sound: &Sound, repeats: u32) -> JoinHandle<()> {
+    let sound = sound.clone();
+    thread::spawn(move || play_sound_blocking(&sound, repeats))
+}
+
+pub fn play_sound_blocking(sound: &Sound, repeats: u32) {
+    let path = match sound {
+        Sound::Attention => match get_attention_tone_path() {
+            Ok(path) => path.to_string_lossy().to_string(),
+            Err(e) => {
+                print_error!("Could not write attention tone: {}", e);
+                return;
+            }
+        },
+        Sound::File(path) => path.clone(),
+        Sound::Theme(name) => {
+            for _ in 0..repeats {
+                if let Err(e) = Command::new("canberra-gtk-play")
+                    .arg("-i")
+                    .arg(name)
+                    .status()
+                {
+                    print_error!(
+                        "Could not play sound {} with canberra-gtk-play: {}",
+                        name,
+                        e
+                    );
+                    return;
+                }
+            }
+            return;
+        }
+    };
+
+    for _ in 0..repeats {
+        if !play_file(&path) {
+            return;
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+}
+
+/// Plays a file with the first audio player that is installed, returns false if none are
+fn play_file(path: &str) -> bool {
+    for player in ["paplay", "pw-play", "aplay"] {
+        match Command::new(player).arg(path).status() {
+            Ok(_) => return true,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => {
+                print_error!("Could not play {} with {}: {}", path, player, e);
+                return false;
+            }
+        }
+    }
+    print_error!("Could not play {}, install paplay, pw-play or aplay", path);
+    false
+}
+
+/// Writes the attention tone to the cache directory the first time it is needed
+fn get_attention_tone_path() -> std::io::Result<PathBuf> {
+    let mut path = dirs::cache_dir().unwrap_or_else(std::env::temp_dir);
+    path.push("nwsd-attention-tone.wav");
+    if !path.exists() {
+        fs::write(&path, generate_attention_tone())?;
+    }
+    Ok(path)
+}
+
+/// A 16 bit mono WAV of the two tone attention signal
+fn generate_attention_tone() -> Vec<u8> {
+    let rate = TONE_SAMPLE_RATE as f64;
+    // Fade each burst in and out over 5ms so it doesn't click
+    let fade = rate * 0.005;
+
+    let mut samples: Vec<i16> = vec![];
+    for (on, off) in TONE_CADENCE {
+        let on_samples = (on * rate) as usize;
+        for i in 0..on_samples {
+            let t = i as f64 / rate;
+            let envelope = (i as f64 / fade)
+                .min((on_samples - i) as f64 / fade)
+                .min(1.0);
+            let value: f64 = TONE_FREQUENCIES
+                .iter()
+                .map(|frequency| (2.0 * PI * frequency * t).sin())
+                .sum::<f64>()
+                / TONE_FREQUENCIES.len() as f64;
+            samples.push((value * envelope * 0.5 * i16::MAX as f64) as i16);
+        }
+        samples.extend(std::iter::repeat_n(0, (off * rate) as usize));
+    }
+
+    let data_length = (samples.len() * 2) as u32;
+    let mut wav: Vec<u8> = Vec::with_capacity(44 + data_length as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_length).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes()); // fmt chunk size
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // Mono
+    wav.extend_from_slice(&TONE_SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(TONE_SAMPLE_RATE * 2).to_le_bytes()); // Byte rate
+    wav.extend_from_slice(&2u16.to_le_bytes()); // Block align
+    wav.extend_from_slice(&16u16.to_le_bytes()); // Bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_length.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
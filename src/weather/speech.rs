@@ -0,0 +1,192 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    sync::Mutex,
+    thread::{self, JoinHandle},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    print_error,
+    weather::{
+        geometry::Proximity,
+        weather::{get_level_for_severity, AlertProperties, Severity},
+    },
+};
+
+/// Only one alert is read aloud at a time so they don't talk over each other
+static SPEAKING: Mutex<()> = Mutex::new(());
+
+/// Shorthand used in NWS products and what it should be read as
+const ABBREVIATIONS: &[(&str, &str)] = &[
+    ("NWS", "National Weather Service"),
+    ("mph", "miles per hour"),
+    ("MPH", "miles per hour"),
+    ("kt", "knots"),
+    ("KT", "knots"),
+    ("ft", "feet"),
+    ("EST", "Eastern Standard Time"),
+    ("EDT", "Eastern Daylight Time"),
+    ("CST", "Central Standard Time"),
+    ("CDT", "Central Daylight Time"),
+    ("MST", "Mountain Standard Time"),
+    ("MDT", "Mountain Daylight Time"),
+    ("PST", "Pacific Standard Time"),
+    ("PDT", "Pacific Daylight Time"),
+    ("AKST", "Alaska Standard Time"),
+    ("AKDT", "Alaska Daylight Time"),
+    ("HST", "Hawaii Standard Time"),
+    ("NNE", "north northeast"),
+    ("ENE", "east northeast"),
+    ("ESE", "east southeast"),
+    ("SSE", "south southeast"),
+    ("SSW", "south southwest"),
+    ("WSW", "west southwest"),
+    ("WNW", "west northwest"),
+    ("NNW", "north northwest"),
+    ("NE", "northeast"),
+    ("SE", "southeast"),
+    ("SW", "southwest"),
+    ("NW", "northwest"),
+    ("mi", "miles"),
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SpeechConfig {
+    pub engine: SpeechEngine,
+    /// The voice model, required for piper
+    pub piper_model: Option<String>,
+    /// Only read alerts at or above this severity
+    #[serde(default = "default_speech_min_severity")]
+    pub min_severity: Severity,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpeechEngine {
+    EspeakNg,
+    Piper,
+    SpeechDispatcher,
+}
+
+fn default_speech_min_severity() -> Severity {
+    Severity::Severe
+}
+
+/// Reads an alert's headline, location and instructions aloud in the background if it is severe
+/// enough
+pub fn speak_alert(
+    alert_properties: &AlertProperties,
+    proximity: Option<&Proximity>,
+    speech_config: &SpeechConfig,
+) -> Option<JoinHandle<()>> {
+    if get_level_for_severity(&alert_properties.severity)
+        < get_level_for_severity(&speech_config.min_severity)
+    {
+        return None;
+    }
+
+    let mut text = alert_properties.headline.clone();
+    if let Some(proximity) = proximity {
+        text = format!("{} {}. {}", alert_properties.event, proximity, text);
+    }
+    if let Some(instruction) = &alert_properties.instruction {
+        text = format!("{}. {}", text, instruction);
+    }
+    let text = expand_abbreviations(&text);
+
+    let speech_config = speech_config.clone();
+    Some(thread::spawn(move || {
+        let _speaking = SPEAKING.lock();
+        if let Err(e) = speak(&text, &speech_config) {
+            print_error!("Could not read alert aloud: {}", e);
+        }
+    }))
+}
+
+/// Pipes text to the configured speech engine and waits for it to finish speaking
+pub fn speak(text: &str, speech_config: &SpeechConfig) -> Result<(), String> {
+    let mut command = match speech_config.engine {
+        SpeechEngine::EspeakNg => {
+            let mut command = Command::new("espeak-ng");
+            command.arg("--stdin");
+            command
+        }
+        SpeechEngine::SpeechDispatcher => {
+            let mut command = Command::new("spd-say");
+            command.args(["--wait", "--pipe-mode"]);
+            command
+        }
+        SpeechEngine::Piper => {
+            let model = speech_config
+                .piper_model
+                .as_ref()
+                .ok_or("piper_model has to be set to use piper")?;
+            let mut command = Command::new("piper");
+            command.args(["--model", model, "--output-raw"]);
+            command
+        }
+    };
+
+    let is_piper = matches!(speech_config.engine, SpeechEngine::Piper);
+    if is_piper {
+        command.stdout(Stdio::piped());
+    }
+
+    let mut engine = command
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not start {:?}: {}", command.get_program(), e))?;
+
+    // piper only synthesizes raw audio, play it with aplay
+    let player = match engine.stdout.take() {
+        Some(audio) if is_piper => Some(
+            Command::new("aplay")
+                .args(["-r", "22050", "-f", "S16_LE", "-t", "raw", "-q", "-"])
+                .stdin(audio)
+                .spawn()
+                .map_err(|e| format!("Could not start aplay: {}", e))?,
+        ),
+        _ => None,
+    };
+
+    if let Some(mut stdin) = engine.stdin.take() {
+        stdin
+            .write_all(text.as_bytes())
+            .map_err(|e| format!("Could not write to speech engine: {}", e))?;
+    }
+
+    engine.wait().map_err(|e| e.to_string())?;
+    if let Some(mut player) = player {
+        player.wait().map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Expands NWS shorthand like "MST" and "mph" into words and smooths out the product formatting
+/// so speech engines read it naturally
+pub fn expand_abbreviations(text: &str) -> String {
+    let text = text
+        .replace("...", ", ")
+        .replace('\n', " ")
+        .replace('*', "");
+
+    let mut expanded = String::with_capacity(text.len());
+    let mut word = String::new();
+    for character in text.chars().chain(std::iter::once(' ')) {
+        if character.is_alphanumeric() {
+            word.push(character);
+            continue;
+        }
+
+        match ABBREVIATIONS.iter().find(|(short, _)| *short == word) {
+            Some((_, long)) => expanded.push_str(long),
+            None => expanded.push_str(&word),
+        }
+        word.clear();
+        expanded.push(character);
+    }
+
+    expanded.trim_end().to_string()
+}
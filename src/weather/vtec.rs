@@ -0,0 +1,519 @@
+use std::{fmt::Display, str::FromStr};
+
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::weather::weather::AlertProperties;
+
+const VTEC_TIME_FORMAT: &str = "%y%m%dT%H%MZ";
+
+/// A P-VTEC code, which tracks a hazard across every product issued about it, e.g.
+/// `/O.NEW.KOUN.TO.W.0045.250519T2010Z-250519T2045Z/`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Vtec {
+    /// `O` for operational products, `T` for tests and `E` or `X` for experimental ones
+    pub product_class: char,
+    pub action: VtecAction,
+    /// The issuing office, e.g. `KOUN`
+    pub office: String,
+    /// The hazard, e.g. `TO` for tornado or `WS` for winter storm
+    pub phenomena: String,
+    pub significance: VtecSignificance,
+    /// Event tracking number, stays the same for every product about one hazard
+    pub event_tracking_number: u32,
+    /// `None` when the event has already begun
+    pub begins: Option<DateTime<Utc>>,
+    /// `None` when the end isn't known
+    pub ends: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum VtecAction {
+    /// NEW
+    New,
+    /// CON
+    Continued,
+    /// EXT, extended in time
+    ExtendedTime,
+    /// EXA, extended in area
+    ExtendedArea,
+    /// EXB, extended in time and area
+    ExtendedBoth,
+    /// UPG, replaced by a more significant product
+    Upgraded,
+    /// CAN
+    Cancelled,
+    /// EXP
+    Expired,
+    /// COR
+    Corrected,
+    /// ROU
+    Routine,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum VtecSignificance {
+    Warning,
+    Watch,
+    Advisory,
+    Statement,
+    Forecast,
+    Outlook,
+    Synopsis,
+}
+
+/// How an alert relates to the alerts that were already notified
+#[derive(Debug, PartialEq)]
+pub enum AlertChange {
+    /// A hazard that wasn't notified yet
+    New,
+    /// A new, more significant product replacing a notified one, e.g. a watch upgraded to a
+    /// warning
+    Upgraded {
+        from: VtecSignificance,
+        to: VtecSignificance,
+    },
+    Extended {
+        until: Option<DateTime<Utc>>,
+    },
+    ExpandedArea,
+    /// The hazard was cancelled or expired
+    Ended,
+    /// A reissue, continuation or correction with nothing new to notify
+    Unchanged,
+}
+
+impl AlertChange {
+    /// What to put in front of the headline of a notification about the change
+    pub fn get_headline_prefix(&self) -> Option<String> {
+        match self {
+            AlertChange::New | AlertChange::Ended | AlertChange::Unchanged => None,
+            AlertChange::Upgraded { from, to } => Some(format!("Upgraded from {} to {}", from, to)),
+            AlertChange::Extended { until: Some(until) } => Some(format!(
+                "Extended until {}",
+                until.with_timezone(&Local).format("%a %-I:%M %p")
+            )),
+            AlertChange::Extended { until: None } => Some("Extended".to_string()),
+            AlertChange::ExpandedArea => Some("Expanded to a larger area".to_string()),
+        }
+    }
+}
+
+/// Works out how an alert relates to the hazards already notified. `is_acknowledged` is asked
+/// about the identities from `AlertProperties::get_identities`
+pub fn get_alert_change(
+    alert_properties: &AlertProperties,
+    is_acknowledged: impl Fn(&str) -> bool,
+) -> AlertChange {
+    if is_acknowledged(&alert_properties.id) {
+        return AlertChange::Unchanged;
+    }
+
+    let vtecs: Vec<&Vtec> = alert_properties
+        .parameters
+        .vtec
+        .iter()
+        .filter(|vtec| vtec.product_class == 'O')
+        .collect();
+    let is_known = |vtec: &Vtec| is_acknowledged(&vtec.get_identity());
+
+    // A product can carry several events, anything new in it is worth notifying
+    if let Some(new) = vtecs
+        .iter()
+        .find(|vtec| vtec.action == VtecAction::New && !is_known(vtec))
+    {
+        return match vtecs
+            .iter()
+            .find(|vtec| vtec.action == VtecAction::Upgraded)
+        {
+            Some(upgraded) => AlertChange::Upgraded {
+                from: upgraded.significance,
+                to: new.significance,
+            },
+            None => AlertChange::New,
+        };
+    }
+
+    if let Some(known) = vtecs.iter().find(|vtec| is_known(vtec)) {
+        // An event split into several alerts, one per group of zones, repeats the same action in
+        // each of them
+        if is_acknowledged(&known.get_action_identity()) {
+            return AlertChange::Unchanged;
+        }
+        return match known.action {
+            VtecAction::ExtendedTime | VtecAction::ExtendedBoth => {
+                AlertChange::Extended { until: known.ends }
+            }
+            VtecAction::ExtendedArea => AlertChange::ExpandedArea,
+            VtecAction::Cancelled | VtecAction::Expired | VtecAction::Upgraded => {
+                AlertChange::Ended
+            }
+            VtecAction::New
+            | VtecAction::Continued
+            | VtecAction::Corrected
+            | VtecAction::Routine => AlertChange::Unchanged,
+        };
+    }
+
+    // Products without VTEC still reference the messages they update
+    if alert_properties
+        .references
+        .iter()
+        .any(|reference| is_acknowledged(&reference.identifier))
+    {
+        return AlertChange::Unchanged;
+    }
+
+    // The end of an event that started before the daemon did, or was forgotten, isn't news
+    if !vtecs.is_empty()
+        && vtecs.iter().all(|vtec| {
+            matches!(
+                vtec.action,
+                VtecAction::Cancelled | VtecAction::Expired | VtecAction::Upgraded
+            )
+        })
+    {
+        return AlertChange::Ended;
+    }
+
+    AlertChange::New
+}
+
+impl Vtec {
+    /// The office, phenomena, significance and event tracking number, e.g. `KOUN.TO.W.0045`.
+    /// These stay the same as the event is continued or extended
+    pub fn get_event_key(&self) -> String {
+        format!(
+            "{}.{}.{}.{:04}",
+            self.office,
+            self.phenomena,
+            self.significance.get_code(),
+            self.event_tracking_number
+        )
+    }
+
+    /// The event key as one of an alert's identities
+    pub fn get_identity(&self) -> String {
+        format!("vtec:{}", self.get_event_key())
+    }
+
+    /// The event key with the action and end time, so an action that was already notified isn't
+    /// notified again for another segment of the event
+    pub fn get_action_identity(&self) -> String {
+        format!(
+            "{}:{}:{}",
+            self.get_identity(),
+            self.action.get_code(),
+            format_vtec_time(self.ends)
+        )
+    }
+}
+
+impl FromStr for Vtec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.trim().trim_matches('/').split('.').collect();
+        let [class, action, office, phenomena, significance, etn, times] = fields[..] else {
+            return Err(format!("{:?} is not a P-VTEC code", s));
+        };
+
+        let product_class = match class {
+            "O" | "T" | "E" | "X" => class.chars().next().unwrap_or('O'),
+            _ => return Err(format!("Unknown VTEC product class {:?}", class)),
+        };
+        let (begins, ends) = times
+            .split_once('-')
+            .ok_or(format!("VTEC times {:?} are missing a begin or end", times))?;
+
+        Ok(Vtec {
+            product_class,
+            action: action.parse()?,
+            office: office.to_string(),
+            phenomena: phenomena.to_string(),
+            significance: significance.parse()?,
+            event_tracking_number: etn
+                .parse()
+                .map_err(|_| format!("VTEC event tracking number {:?} is not a number", etn))?,
+            begins: parse_vtec_time(begins)?,
+            ends: parse_vtec_time(ends)?,
+        })
+    }
+}
+
+/// All zeros means the time doesn't apply
+fn parse_vtec_time(time: &str) -> Result<Option<DateTime<Utc>>, String> {
+    if time == "000000T0000Z" {
+        return Ok(None);
+    }
+    NaiveDateTime::parse_from_str(time, VTEC_TIME_FORMAT)
+        .map(|time| Some(time.and_utc()))
+        .map_err(|e| format!("VTEC time {:?} is invalid: {}", time, e))
+}
+
+fn format_vtec_time(time: Option<DateTime<Utc>>) -> String {
+    match time {
+        Some(time) => time.format(VTEC_TIME_FORMAT).to_string(),
+        None => "000000T0000Z".to_string(),
+    }
+}
+
+impl Display for Vtec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "/{}.{}.{}.{}.{}.{:04}.{}-{}/",
+            self.product_class,
+            self.action.get_code(),
+            self.office,
+            self.phenomena,
+            self.significance.get_code(),
+            self.event_tracking_number,
+            format_vtec_time(self.begins),
+            format_vtec_time(self.ends)
+        )
+    }
+}
+
+impl TryFrom<String> for Vtec {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Vtec> for String {
+    fn from(vtec: Vtec) -> Self {
+        vtec.to_string()
+    }
+}
+
+const ACTION_CODES: [(VtecAction, &str); 10] = [
+    (VtecAction::New, "NEW"),
+    (VtecAction::Continued, "CON"),
+    (VtecAction::ExtendedTime, "EXT"),
+    (VtecAction::ExtendedArea, "EXA"),
+    (VtecAction::ExtendedBoth, "EXB"),
+    (VtecAction::Upgraded, "UPG"),
+    (VtecAction::Cancelled, "CAN"),
+    (VtecAction::Expired, "EXP"),
+    (VtecAction::Corrected, "COR"),
+    (VtecAction::Routine, "ROU"),
+];
+
+impl VtecAction {
+    pub fn get_code(&self) -> &'static str {
+        ACTION_CODES
+            .iter()
+            .find(|(action, _)| action == self)
+            .map(|(_, code)| *code)
+            .unwrap_or("ROU")
+    }
+}
+
+impl FromStr for VtecAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ACTION_CODES
+            .iter()
+            .find(|(_, code)| *code == s)
+            .map(|(action, _)| *action)
+            .ok_or(format!("Unknown VTEC action {:?}", s))
+    }
+}
+
+const SIGNIFICANCE_CODES: [(VtecSignificance, &str); 7] = [
+    (VtecSignificance::Warning, "W"),
+    (VtecSignificance::Watch, "A"),
+    (VtecSignificance::Advisory, "Y"),
+    (VtecSignificance::Statement, "S"),
+    (VtecSignificance::Forecast, "F"),
+    (VtecSignificance::Outlook, "O"),
+    (VtecSignificance::Synopsis, "N"),
+];
+
+impl VtecSignificance {
+    pub fn get_code(&self) -> &'static str {
+        SIGNIFICANCE_CODES
+            .iter()
+            .find(|(significance, _)| significance == self)
+            .map(|(_, code)| *code)
+            .unwrap_or("S")
+    }
+}
+
+impl FromStr for VtecSignificance {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SIGNIFICANCE_CODES
+            .iter()
+            .find(|(_, code)| *code == s)
+            .map(|(significance, _)| *significance)
+            .ok_or(format!("Unknown VTEC significance {:?}", s))
+    }
+}
+
+impl Display for VtecSignificance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::weather::weather::{generate_test_alert, Severity};
+
+    const NEW_WARNING: &str = "/O.NEW.KOUN.TO.W.0045.250519T2010Z-250519T2045Z/";
+
+    fn alert(id: &str, codes: &[&str]) -> AlertProperties {
+        let mut alert = generate_test_alert(&Severity::Extreme);
+        alert.id = id.to_string();
+        alert.parameters.vtec = codes.iter().map(|code| code.parse().unwrap()).collect();
+        alert
+    }
+
+    fn get_change(alert: &AlertProperties, acknowledged: &HashSet<String>) -> AlertChange {
+        get_alert_change(alert, |identity| acknowledged.contains(identity))
+    }
+
+    #[test]
+    fn parses_pvtec() {
+        let vtec: Vtec = NEW_WARNING.parse().unwrap();
+
+        assert_eq!(vtec.product_class, 'O');
+        assert_eq!(vtec.action, VtecAction::New);
+        assert_eq!(vtec.office, "KOUN");
+        assert_eq!(vtec.phenomena, "TO");
+        assert_eq!(vtec.significance, VtecSignificance::Warning);
+        assert_eq!(vtec.event_tracking_number, 45);
+        assert_eq!(
+            vtec.begins,
+            Some(Utc.with_ymd_and_hms(2025, 5, 19, 20, 10, 0).unwrap())
+        );
+        assert_eq!(
+            vtec.ends,
+            Some(Utc.with_ymd_and_hms(2025, 5, 19, 20, 45, 0).unwrap())
+        );
+        assert_eq!(vtec.get_event_key(), "KOUN.TO.W.0045");
+        assert_eq!(vtec.to_string(), NEW_WARNING);
+    }
+
+    #[test]
+    fn parses_pvtec_without_begin_time() {
+        let vtec: Vtec = "/O.EXT.KOUN.WS.A.0003.000000T0000Z-250120T1200Z/"
+            .parse()
+            .unwrap();
+
+        assert_eq!(vtec.action, VtecAction::ExtendedTime);
+        assert_eq!(vtec.significance, VtecSignificance::Watch);
+        assert_eq!(vtec.begins, None);
+    }
+
+    #[test]
+    fn rejects_malformed_pvtec() {
+        for code in [
+            "",
+            "/O.NEW.KOUN.TO.W/",
+            "/O.XYZ.KOUN.TO.W.0045.250519T2010Z-250519T2045Z/",
+            "/O.NEW.KOUN.TO.Q.0045.250519T2010Z-250519T2045Z/",
+            "/O.NEW.KOUN.TO.W.00A5.250519T2010Z-250519T2045Z/",
+            "/O.NEW.KOUN.TO.W.0045.250519T2010Z/",
+            "/O.NEW.KOUN.TO.W.0045.251319T2010Z-250519T2045Z/",
+        ] {
+            assert!(code.parse::<Vtec>().is_err(), "{:?} parsed", code);
+        }
+    }
+
+    #[test]
+    fn new_event_is_new() {
+        let warning = alert("1", &[NEW_WARNING]);
+        assert_eq!(get_change(&warning, &HashSet::new()), AlertChange::New);
+    }
+
+    #[test]
+    fn extension_of_notified_event_is_notified_once() {
+        let mut acknowledged: HashSet<String> = alert("1", &[NEW_WARNING])
+            .get_identities()
+            .into_iter()
+            .collect();
+
+        // The extension covers two groups of zones, each in its own alert
+        let code = "/O.EXT.KOUN.TO.W.0045.000000T0000Z-250519T2115Z/";
+        let first = alert("2", &[code]);
+        assert_eq!(
+            get_change(&first, &acknowledged),
+            AlertChange::Extended {
+                until: Some(Utc.with_ymd_and_hms(2025, 5, 19, 21, 15, 0).unwrap())
+            }
+        );
+        acknowledged.extend(first.get_identities());
+
+        let second = alert("3", &[code]);
+        assert_eq!(get_change(&second, &acknowledged), AlertChange::Unchanged);
+    }
+
+    #[test]
+    fn continuation_is_unchanged() {
+        let acknowledged: HashSet<String> = alert("1", &[NEW_WARNING])
+            .get_identities()
+            .into_iter()
+            .collect();
+        let continued = alert("2", &["/O.CON.KOUN.TO.W.0045.000000T0000Z-250519T2045Z/"]);
+
+        assert_eq!(
+            get_change(&continued, &acknowledged),
+            AlertChange::Unchanged
+        );
+    }
+
+    #[test]
+    fn cancellation_ends_the_event() {
+        let acknowledged: HashSet<String> = alert("1", &[NEW_WARNING])
+            .get_identities()
+            .into_iter()
+            .collect();
+        let cancelled = alert("2", &["/O.CAN.KOUN.TO.W.0045.000000T0000Z-250519T2045Z/"]);
+
+        assert_eq!(get_change(&cancelled, &acknowledged), AlertChange::Ended);
+    }
+
+    #[test]
+    fn cancellation_of_unknown_event_ends_it() {
+        let cancelled = alert("2", &["/O.CAN.KOUN.TO.W.0045.000000T0000Z-250519T2045Z/"]);
+
+        assert_eq!(get_change(&cancelled, &HashSet::new()), AlertChange::Ended);
+    }
+
+    #[test]
+    fn watch_upgraded_to_warning() {
+        let acknowledged: HashSet<String> =
+            alert("1", &["/O.NEW.KOUN.TO.A.0210.250519T1900Z-250520T0200Z/"])
+                .get_identities()
+                .into_iter()
+                .collect();
+        let upgraded = alert(
+            "2",
+            &[
+                "/O.UPG.KOUN.TO.A.0210.000000T0000Z-250520T0200Z/",
+                NEW_WARNING,
+            ],
+        );
+
+        assert_eq!(
+            get_change(&upgraded, &acknowledged),
+            AlertChange::Upgraded {
+                from: VtecSignificance::Watch,
+                to: VtecSignificance::Warning
+            }
+        );
+    }
+}
@@ -1,22 +1,43 @@
+use chrono::{DateTime, FixedOffset, Local};
 use notify_rust::{Notification, Urgency};
-use std::{fmt::Display, str::FromStr};
+use std::{
+    fmt::Display,
+    str::FromStr,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
 
 use efcl::{color, Color};
-use serde::Deserialize;
-
-use crate::daemon::Config;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    daemon::{
+        metrics::{get_endpoint_label, FetchErrorKind, METRICS},
+        Config, Location,
+    },
+    print_warn,
+    weather::{
+        emergency::show_emergency_alert,
+        geometry::{Geometry, Proximity},
+        parameters::AlertParameters,
+        sound::{get_repeats_for_alert, get_sound_for_alert, play_sound, Sound},
+        speech::speak_alert,
+    },
+};
 
 #[derive(Debug, Deserialize)]
 pub struct GeoJson {
     features: Vec<Feature>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Feature {
     pub properties: AlertProperties,
+    #[serde(default)]
+    pub geometry: Option<Geometry>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
 pub enum Severity {
     Extreme,
     Severe,
@@ -26,7 +47,7 @@ pub enum Severity {
 }
 
 // Thanks GPT!
-#[derive(Debug, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
 pub enum Event {
     #[serde(rename = "Hazardous Weather Outlook")]
     HazardousWeatherOutlook,
@@ -34,6 +55,10 @@ pub enum Event {
     #[serde(rename = "Test")]
     Test,
 
+    /// Synthetic alerts raised by a configured forecast rule
+    #[serde(rename = "Forecast Trigger")]
+    ForecastTrigger,
+
     // Winter Weather/Cold Weather
     #[serde(rename = "Winter Storm Watch")]
     WinterStormWatch,
@@ -135,21 +160,254 @@ pub enum Event {
     Unknown,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct AlertProperties {
     pub headline: String,
     pub description: String,
     pub severity: Severity,
     pub id: String,
     pub event: Event,
+    /// What people in the alert area should do
+    #[serde(default)]
+    pub instruction: Option<String>,
+    #[serde(default)]
+    pub parameters: AlertParameters,
+    #[serde(default)]
+    pub sent: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    pub effective: Option<DateTime<FixedOffset>>,
+    /// When the hazard is expected to begin
+    #[serde(default)]
+    pub onset: Option<DateTime<FixedOffset>>,
+    /// When this message expires, the alert may still be updated or extended after
+    #[serde(default)]
+    pub expires: Option<DateTime<FixedOffset>>,
+    /// When the hazard is expected to end
+    #[serde(default)]
+    pub ends: Option<DateTime<FixedOffset>>,
+    /// Earlier messages this one updates or cancels
+    #[serde(default)]
+    pub references: Vec<AlertReference>,
+}
+
+/// A reference to an earlier alert message by its CAP identifier
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AlertReference {
+    pub identifier: String,
+    #[serde(default)]
+    pub sent: Option<DateTime<FixedOffset>>,
+}
+
+impl AlertProperties {
+    /// When the alert stops being relevant, the end of the hazard if known otherwise when the
+    /// message expires
+    pub fn get_end(&self) -> Option<DateTime<FixedOffset>> {
+        self.ends.or(self.expires)
+    }
+
+    pub fn is_expired(&self, now: DateTime<Local>) -> bool {
+        self.get_end().is_some_and(|end| end < now)
+    }
+
+    /// Everything that identifies the hazard an alert is about: its own ID, the IDs of the
+    /// messages it updates and its VTEC events, with and without their action. Alerts that share
+    /// any of these are updates of the same hazard
+    pub fn get_identities(&self) -> Vec<String> {
+        let mut identities = vec![self.id.clone()];
+        identities.extend(
+            self.references
+                .iter()
+                .map(|reference| reference.identifier.clone()),
+        );
+        for vtec in &self.parameters.vtec {
+            identities.push(vtec.get_identity());
+            identities.push(vtec.get_action_identity());
+        }
+        identities
+    }
+
+    /// The onset if it is in the future
+    pub fn get_future_onset(&self, now: DateTime<Local>) -> Option<DateTime<FixedOffset>> {
+        self.onset.filter(|onset| *onset > now)
+    }
+
+    /// Onset and end in local time, e.g. "From Thu 3:00 PM until Fri 5:00 AM"
+    pub fn get_time_summary(&self) -> Option<String> {
+        let format = |time: DateTime<FixedOffset>| {
+            time.with_timezone(&Local)
+                .format("%a %-I:%M %p")
+                .to_string()
+        };
+
+        match (self.onset.or(self.effective), self.get_end()) {
+            (Some(start), Some(end)) => {
+                Some(format!("From {} until {}", format(start), format(end)))
+            }
+            (Some(start), None) => Some(format!("From {}", format(start))),
+            (None, Some(end)) => Some(format!("Until {}", format(end))),
+            (None, None) => None,
+        }
+    }
+}
+
+pub fn extract_weather_features(json_data: String) -> Result<Vec<Feature>, String> {
+    let geo_json: GeoJson = serde_json::from_str(&json_data)
+        .map_err(|e| format!("Failed to deserialize JSON: {}", e))?;
+    Ok(geo_json.features)
+}
+
+/// Performs a GET request against the NWS API with the configured user agent, returning the body
+pub fn fetch_text(config: &Config, url: &str) -> Result<String, String> {
+    let client = reqwest::blocking::Client::builder()
+        .user_agent(&config.user_agent)
+        .build()
+        .map_err(|e| format!("Could not build request client: {}", e))?;
+
+    let timer = METRICS
+        .request_duration
+        .with_label_values(&[get_endpoint_label(url)])
+        .start_timer();
+    let response = client.get(url).send().map_err(|e| {
+        METRICS.record_fetch_error(if e.is_timeout() {
+            FetchErrorKind::Timeout
+        } else if e.is_connect() {
+            FetchErrorKind::Connect
+        } else {
+            FetchErrorKind::Request
+        });
+        format!("Failed to fetch data. Response: {}", e)
+    })?;
+
+    // Ensure the request was successful
+    if !response.status().is_success() {
+        METRICS.record_fetch_error(FetchErrorKind::Status);
+        return Err(format!(
+            "Failed to fetch data. Response: {}",
+            response.text().unwrap_or("None".to_string())
+        ));
+    }
+
+    let text = response.text().map_err(|e| {
+        METRICS.record_fetch_error(FetchErrorKind::Body);
+        format!("Failed to read response text: {}", e)
+    });
+    timer.observe_duration();
+    text
+}
+
+/// Fetches the raw active alerts response for a location
+pub fn fetch_weather_text(config: &Config, location: &Location) -> Result<String, String> {
+    fetch_text(config, &get_alerts_url(location))
+}
+
+/// Fetches and parses the active alerts for a location
+pub fn fetch_weather_features(
+    config: &Config,
+    location: &Location,
+) -> Result<Vec<Feature>, String> {
+    let features = extract_weather_features(fetch_weather_text(config, location)?)?;
+    Ok(filter_features_for_location(features, location, config))
+}
+
+/// Drops expired alerts, and alerts whose polygon doesn't cover the location or come within
+/// `nearby_radius_miles` of it when `require_inside_polygon` is set. Alerts without a polygon are
+/// always kept
+pub fn filter_features_for_location(
+    features: Vec<Feature>,
+    location: &Location,
+    config: &Config,
+) -> Vec<Feature> {
+    let now = Local::now();
+    let features = features
+        .into_iter()
+        .filter(|feature| !feature.properties.is_expired(now));
+
+    if !config.require_inside_polygon {
+        return features.collect();
+    }
+
+    features
+        .filter(|feature| match &feature.geometry {
+            Some(geometry) => match geometry.proximity(location) {
+                Some(proximity) => proximity.miles <= config.nearby_radius_miles,
+                None => geometry.contains(location),
+            },
+            None => true,
+        })
+        .collect()
+}
+
+/// How far the alert's polygon is from a location, `None` when the location is inside it or the
+/// alert has no polygon
+pub fn get_proximity(feature: &Feature, location: &Location) -> Option<Proximity> {
+    feature.geometry.as_ref()?.proximity(location)
+}
+
+/// Fetches the active alerts for every configured location, skipping alerts that cover more than
+/// one of them. A location that can't be fetched is reported on stderr, which status bars don't
+/// display, and skipped. Errors when none of them could be fetched
+pub fn fetch_all_weather_features(config: &Config) -> Result<Vec<Feature>, String> {
+    let mut features: Vec<Feature> = vec![];
+    let mut first_error = None;
+    let mut fetched = false;
+    for location in config.get_locations() {
+        let location_features = match fetch_weather_features(config, &location) {
+            Ok(location_features) => location_features,
+            Err(e) => {
+                let error = format!("Could not fetch alerts for {}: {}", location.name, e);
+                eprintln!("{}", error);
+                first_error.get_or_insert(error);
+                continue;
+            }
+        };
+        fetched = true;
+        for feature in location_features {
+            if !features
+                .iter()
+                .any(|existing| existing.properties.id == feature.properties.id)
+            {
+                features.push(feature);
+            }
+        }
+    }
+    match (fetched, first_error) {
+        (false, Some(error)) => Err(error),
+        _ => Ok(features),
+    }
 }
 
-pub fn extract_weather_features(json_data: String) -> Vec<Feature> {
-    let geo_json: GeoJson = serde_json::from_str(&json_data).expect("Failed to deserialize JSON");
-    geo_json.features
+/// How many times to try showing a notification before giving up on the notification server
+const NOTIFICATION_ATTEMPTS: u32 = 3;
+const NOTIFICATION_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+pub fn get_alerts_url(location: &Location) -> String {
+    format!(
+        "https://api.weather.gov/alerts/active?point={},{}",
+        location.lat, location.lon
+    )
 }
 
-pub fn send_notification(alert_properties: &AlertProperties, config: &Config) {
+/// Sends the desktop notification for an alert. Extreme alerts are shown with the emergency
+/// alert presentation instead when `extreme_alert_window` is set, the returned handle finishes
+/// once it has been acknowledged. Errors when neither could be shown after a few attempts. The
+/// alert's sound and speech only play once it has been shown, so retries aren't heard
+pub fn send_notification(
+    alert_properties: &AlertProperties,
+    proximity: Option<&Proximity>,
+    config: &Config,
+) -> Result<Option<JoinHandle<()>>, String> {
+    let severity = format!("{:?}", alert_properties.severity);
+
+    if let Severity::Extreme = alert_properties.severity {
+        if config.extreme_alert_window {
+            // The emergency alert plays its own sound
+            let handle = show_emergency_alert(alert_properties, proximity, config)?;
+            METRICS.record_notification("emergency_window", &severity);
+            play_alert_audio(alert_properties, proximity, config, false);
+            return Ok(Some(handle));
+        }
+    }
+
     let icon_path = match &config.notification_icon_path {
         Some(a) => a,
         None => &format!(
@@ -158,38 +416,97 @@ pub fn send_notification(alert_properties: &AlertProperties, config: &Config) {
         ),
     };
 
-    let body = match config.detailed_notification {
-        true => &alert_properties.description,
-        false => &alert_properties.headline,
+    let mut body = match config.detailed_notification {
+        true => alert_properties.description.clone(),
+        false => alert_properties.headline.clone(),
     };
 
+    if let Some(proximity) = proximity {
+        body = format!("{} {}\n{}", alert_properties.event, proximity, body);
+    }
+
+    if let Some(times) = alert_properties.get_time_summary() {
+        body = format!("{}\n{}", body, times);
+    }
+
+    if config.detailed_notification {
+        if let Some(summary) = alert_properties.parameters.summary() {
+            body = format!("{}\n\n{}", summary, body);
+        }
+    }
+
     let timeout = match &alert_properties.severity {
         Severity::Extreme | Severity::Severe | Severity::Moderate | Severity::Unknown => 0, // Never timeout
         Severity::Minor => 120 * 1000,
     };
 
-    Notification::new()
+    let mut notification = Notification::new();
+    notification
         .summary(format!("{:?} Weather Alert", &alert_properties.severity).as_str())
-        .body(body)
+        .body(&body)
         .icon(icon_path)
         .appname("National Weather Service Daemon")
         .urgency(get_notification_urgency_for_severity(
             &alert_properties.severity,
         ))
-        .timeout(timeout)
-        .show()
-        .unwrap();
+        .timeout(timeout);
+
+    // The notification server can play a theme sound once by itself
+    let theme_sound = match get_sound_for_alert(alert_properties, config) {
+        Some(Sound::Theme(name)) if get_repeats_for_alert(alert_properties, config) == 1 => {
+            notification.sound_name(name);
+            true
+        }
+        _ => false,
+    };
+
+    // The notification server may not be up yet right after logging in
+    for attempt in 1..=NOTIFICATION_ATTEMPTS {
+        match notification.show() {
+            Ok(_) => break,
+            Err(e) if attempt < NOTIFICATION_ATTEMPTS => {
+                print_warn!(format!("Could not show notification, trying again: {}", e));
+                thread::sleep(NOTIFICATION_RETRY_DELAY);
+            }
+            Err(e) => {
+                return Err(format!(
+                    "Could not show notification after {} attempts: {}",
+                    NOTIFICATION_ATTEMPTS, e
+                ))
+            }
+        }
+    }
+    METRICS.record_notification("desktop", &severity);
+    play_alert_audio(alert_properties, proximity, config, !theme_sound);
+
+    Ok(None)
+}
+
+/// Reads an alert aloud and plays its sound once it has been delivered, `with_sound` is false
+/// when the sound was already played along with it
+pub fn play_alert_audio(
+    alert_properties: &AlertProperties,
+    proximity: Option<&Proximity>,
+    config: &Config,
+    with_sound: bool,
+) {
+    let severity = format!("{:?}", alert_properties.severity);
+    if let Some(speech_config) = &config.speech {
+        if speak_alert(alert_properties, proximity, speech_config).is_some() {
+            METRICS.record_notification("speech", &severity);
+        }
+    }
+
+    if with_sound {
+        if let Some(sound) = get_sound_for_alert(alert_properties, config) {
+            play_sound(sound, get_repeats_for_alert(alert_properties, config));
+        }
+    }
 }
 
 impl Display for Feature {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let severity_color = match self.properties.severity {
-            Severity::Extreme => Color::PURPLE,
-            Severity::Severe => Color::RED,
-            Severity::Moderate => Color::YELLOW,
-            Severity::Minor => Color::GREEN,
-            Severity::Unknown => Color::LIGHTGRAY,
-        };
+        let severity_color = get_color_for_severity(&self.properties.severity);
 
         write!(
             f,
@@ -199,7 +516,50 @@ impl Display for Feature {
                 format!("{}:", self.properties.event).as_str()
             ),
             self.properties.headline.as_str()
-        )
+        )?;
+
+        if let Some(times) = self.properties.get_time_summary() {
+            write!(f, "\n    {}", times)?;
+        }
+        if let Some(summary) = self.properties.parameters.summary() {
+            write!(f, "\n    {}", summary)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn get_color_for_severity(severity: &Severity) -> Color {
+    match severity {
+        Severity::Extreme => Color::PURPLE,
+        Severity::Severe => Color::RED,
+        Severity::Moderate => Color::YELLOW,
+        Severity::Minor => Color::GREEN,
+        Severity::Unknown => Color::LIGHTGRAY,
+    }
+}
+
+/// Hex equivalent of a terminal color, for outputs that can't use ANSI escapes (status bars)
+pub fn get_hex_for_color(color: Color) -> &'static str {
+    match color {
+        Color::BLACK => "#000000",
+        Color::RED => "#ff5555",
+        Color::GREEN => "#50fa7b",
+        Color::YELLOW => "#f1fa8c",
+        Color::BLUE => "#6272a4",
+        Color::PURPLE => "#bd93f9",
+        Color::CYAN => "#8be9fd",
+        Color::LIGHTGRAY => "#d3d3d3",
+    }
+}
+
+/// Orders severities from least (Unknown) to most (Extreme) severe
+pub fn get_level_for_severity(severity: &Severity) -> u8 {
+    match severity {
+        Severity::Extreme => 4,
+        Severity::Severe => 3,
+        Severity::Moderate => 2,
+        Severity::Minor => 1,
+        Severity::Unknown => 0,
     }
 }
 
@@ -272,6 +632,7 @@ pub fn get_icon_for_event(event: &Event) -> &'static str {
         // Fallback for unknown events
         Event::Unknown | Event::HazardousWeatherOutlook => "weather-severe-alert-symbolic.svg",
         Event::Test => "weather-severe-alert-symbolic.svg",
+        Event::ForecastTrigger => "weather-few-clouds-symbolic.svg",
     }
 }
 
@@ -323,6 +684,7 @@ impl Display for Event {
             Event::HurricaneWarning => "Hurricane Warning",
             Event::Unknown => "Unknown",
             Event::Test => "Test",
+            Event::ForecastTrigger => "Forecast Trigger",
         };
         write!(f, "{}", readable)
     }
@@ -356,5 +718,13 @@ pub fn generate_test_alert(severity: &Severity) -> AlertProperties {
         severity: severity.clone(),
         id: "urn:oid:2.49.0.1.840.0.4b440460568820c3135c6fa9bb92f30c621509d8.003.1".to_string(),
         event: Event::Test,
+        instruction: Some("This is only a test. No action is required.".to_string()),
+        parameters: AlertParameters::default(),
+        sent: None,
+        effective: None,
+        onset: None,
+        expires: None,
+        ends: None,
+        references: Vec::new(),
     }
 }